@@ -0,0 +1,36 @@
+//! Times `search` against `par_search` on a large generated corpus
+//!
+//! Run with `cargo run --release --example par_search --features rayon`.
+
+use fuzzy_search::FuzzyConfig;
+use std::time::Instant;
+
+fn main() {
+    let corpus: Vec<String> = (0..200_000)
+        .map(|i| match i % 4 {
+            0 => format!("item {} hello world", i),
+            1 => format!("helo wrld {}", i),
+            2 => format!("unrelated entry {}", i),
+            _ => format!("{} HELLO there, world", i),
+        })
+        .collect();
+    let items: Vec<&str> = corpus.iter().map(String::as_str).collect();
+    let matcher = FuzzyConfig::new("hello world").compiled().unwrap();
+
+    let start = Instant::now();
+    let sequential = matcher.search(items.iter().copied());
+    let sequential_time = start.elapsed();
+    let start = Instant::now();
+    let parallel = matcher.par_search(&items);
+    let parallel_time = start.elapsed();
+    assert_eq!(parallel, sequential);
+
+    println!(
+        "{} matches of {} items; search: {:?}, par_search: {:?} on {} threads",
+        parallel.len(),
+        items.len(),
+        sequential_time,
+        parallel_time,
+        rayon::current_num_threads()
+    );
+}
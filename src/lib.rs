@@ -1,4 +1,6 @@
 use log::{debug, error, warn};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 /// A Rust library for fuzzy text searching with regex pattern generation.
 ///
 /// This library provides flexible pattern matching that's tolerant to typos
@@ -41,7 +43,11 @@ use log::{debug, error, warn};
 /// ```
 use std::error::Error;
 use std::fmt;
+use std::io::{self, BufRead};
+use std::ops::Range;
+use std::sync::Arc;
 use typed_builder::TypedBuilder;
+use unicode_segmentation::UnicodeSegmentation;
 
 /// Custom error types for fuzzy search operations
 #[derive(Debug)]
@@ -52,6 +58,8 @@ pub enum FuzzyError {
     RegexError(Box<fancy_regex::Error>),
     /// Empty pattern
     EmptyPattern,
+    /// Pattern that matches every haystack, rejected by `reject_trivial`
+    TrivialPattern,
 }
 
 impl fmt::Display for FuzzyError {
@@ -60,6 +68,7 @@ impl fmt::Display for FuzzyError {
             FuzzyError::InvalidPattern(msg) => write!(f, "Invalid pattern: {}", msg),
             FuzzyError::RegexError(err) => write!(f, "Regex error: {}", err),
             FuzzyError::EmptyPattern => write!(f, "Empty pattern"),
+            FuzzyError::TrivialPattern => write!(f, "Pattern matches everything"),
         }
     }
 }
@@ -73,8 +82,287 @@ impl From<fancy_regex::Error> for FuzzyError {
     }
 }
 
+/// Estimated risk of catastrophic backtracking for a generated pattern
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RiskLevel {
+    /// Bounded ambiguity, safe for untrusted input
+    Low,
+    /// Several overlapping optional or unbounded parts
+    Medium,
+    /// Nested quantifiers that can backtrack exponentially
+    High,
+}
+
+/// Counters collected while walking a parsed pattern for backtracking analysis
+#[derive(Debug, Default)]
+struct RiskCounters {
+    /// Variable repetitions nested inside another variable repetition
+    nested: usize,
+    /// Optional groups wrapping variable-width repetitions
+    optional_groups: usize,
+    /// Unbounded repetitions such as `.*?`
+    unbounded: usize,
+    /// Look-around assertions
+    lookarounds: usize,
+}
+
+impl RiskCounters {
+    fn visit(&mut self, expr: &fancy_regex::Expr, inside_repeat: bool) {
+        use fancy_regex::Expr;
+        match expr {
+            Expr::Concat(children) | Expr::Alt(children) => {
+                for child in children {
+                    self.visit(child, inside_repeat);
+                }
+            }
+            Expr::Group(child) | Expr::AtomicGroup(child) => self.visit(child, inside_repeat),
+            Expr::LookAround(child, _) => {
+                self.lookarounds += 1;
+                self.visit(child, inside_repeat);
+            }
+            Expr::Repeat { child, lo, hi, .. } => {
+                let variable = hi > lo && *hi > 1;
+                if variable && inside_repeat {
+                    self.nested += 1;
+                }
+                if *hi == usize::MAX {
+                    self.unbounded += 1;
+                }
+                if *lo == 0 && *hi == 1 && contains_variable_repeat(child) {
+                    self.optional_groups += 1;
+                }
+                self.visit(child, inside_repeat || variable);
+            }
+            Expr::Conditional {
+                condition,
+                true_branch,
+                false_branch,
+            } => {
+                self.visit(condition, inside_repeat);
+                self.visit(true_branch, inside_repeat);
+                self.visit(false_branch, inside_repeat);
+            }
+            _ => {}
+        }
+    }
+
+    fn level(&self) -> RiskLevel {
+        if self.nested > 0 {
+            RiskLevel::High
+        } else if self.optional_groups > 2 || self.unbounded > 4 || self.lookarounds > 0 {
+            RiskLevel::Medium
+        } else {
+            RiskLevel::Low
+        }
+    }
+}
+
+/// Returns true if the expression contains a repetition of variable width
+fn contains_variable_repeat(expr: &fancy_regex::Expr) -> bool {
+    use fancy_regex::Expr;
+    match expr {
+        Expr::Concat(children) | Expr::Alt(children) => {
+            children.iter().any(contains_variable_repeat)
+        }
+        Expr::Group(child) | Expr::AtomicGroup(child) | Expr::LookAround(child, _) => {
+            contains_variable_repeat(child)
+        }
+        Expr::Repeat { child, lo, hi, .. } => hi > lo && *hi > 1 || contains_variable_repeat(child),
+        _ => false,
+    }
+}
+
+/// Counts the nodes of a parsed pattern
+fn count_nodes(expr: &fancy_regex::Expr) -> usize {
+    use fancy_regex::Expr;
+    1 + match expr {
+        Expr::Concat(children) | Expr::Alt(children) => children.iter().map(count_nodes).sum(),
+        Expr::Group(child)
+        | Expr::AtomicGroup(child)
+        | Expr::LookAround(child, _)
+        | Expr::Repeat { child, .. } => count_nodes(child),
+        Expr::Conditional {
+            condition,
+            true_branch,
+            false_branch,
+        } => count_nodes(condition) + count_nodes(true_branch) + count_nodes(false_branch),
+        _ => 0,
+    }
+}
+
+/// Counts the nodes of a pattern's syntax tree, or 0 if it doesn't parse
+fn pattern_complexity(pattern: &str) -> usize {
+    match fancy_regex::Expr::parse_tree(pattern) {
+        Ok(tree) => count_nodes(&tree.expr),
+        Err(err) => {
+            warn!("Could not analyze pattern {}: {}", pattern, err);
+            0
+        }
+    }
+}
+
+/// Order bonus used by `soft_order` when no `order_bonus` is set
+pub const SOFT_ORDER_BONUS: f32 = 0.2;
+
+/// Largest `max_char_gap` accepted by [`FuzzyConfig::validate`]
+pub const MAX_CHAR_GAP: usize = 1000;
+
+/// Maximum adjacent character pairs per word that `transposition_tolerance` swaps
+pub const MAX_TRANSPOSITION_PAIRS: usize = 8;
+
+/// ANSI escape that starts highlighting in `search_display` output
+pub const ANSI_HIGHLIGHT: &str = "\x1b[1;31m";
+
+/// ANSI escape that ends highlighting in `search_display` output
+pub const ANSI_RESET: &str = "\x1b[0m";
+
+/// Bytes of a line `search_lines` keeps; the rest of a longer line is skipped
+pub const MAX_LINE_LEN: usize = 1 << 20;
+
+/// A custom text normalization applied to the search term
+#[derive(Clone)]
+pub struct Normalizer(Arc<dyn Fn(&str) -> String + Send + Sync>);
+
+impl fmt::Debug for Normalizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Normalizer(..)")
+    }
+}
+
+/// A matched region of a haystack
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzyMatch {
+    /// Byte offset where the match starts
+    pub start: usize,
+    /// Byte offset where the match ends
+    pub end: usize,
+    /// The matched text
+    pub text: String,
+}
+
+/// Byte offsets of one match, as found by `find_all`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MatchRange {
+    /// Byte offset where the match starts
+    pub start: usize,
+    /// Byte offset where the match ends
+    pub end: usize,
+}
+
+/// A run of text that is either all matched or all unmatched
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    /// The text of the run
+    pub text: String,
+    /// Whether the run is part of the match
+    pub matched: bool,
+}
+
+/// A search result ready to print in a terminal
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplayResult<'a> {
+    /// The matching candidate
+    pub candidate: &'a str,
+    /// The match and its context, with matched spans highlighted in ANSI
+    pub display: String,
+    /// The score of the match
+    pub score: f32,
+}
+
+/// How a query character matched a haystack character
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharMatchKind {
+    /// The same character
+    Exact,
+    /// The same letter in a different case
+    CaseFold,
+    /// The same base letter with different accents
+    DiacriticFold,
+    /// An equivalent character, such as a leetspeak substitute or adjacent key
+    Equivalence,
+}
+
+/// A query character and the haystack character it matched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CharMatch {
+    /// The character of the search term
+    pub query: char,
+    /// Byte offset of the matched character in the haystack
+    pub position: usize,
+    /// How the characters matched
+    pub kind: CharMatchKind,
+}
+
+/// How a word's characters split into required and optional ones
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WordAnalysis {
+    /// Leading characters that must be present, in order
+    pub required: Vec<char>,
+    /// Trailing characters that may be missing
+    pub optional: Vec<char>,
+    /// Number of required characters
+    pub required_chars: usize,
+}
+
+/// Each query word paired with the byte ranges where it matched
+pub type WordMatches = Vec<(String, Vec<Range<usize>>)>;
+
+/// A single edit turning the search term into matched text
+///
+/// Positions are character offsets into the search term.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditOp {
+    /// `ch` is inserted before the term character at `position`
+    Insert { position: usize, ch: char },
+    /// The term character `ch` at `position` is removed
+    Delete { position: usize, ch: char },
+    /// The term character `from` at `position` is replaced by `to`
+    Substitute {
+        position: usize,
+        from: char,
+        to: char,
+    },
+}
+
+/// Broad character categories used to tune gaps between characters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CharCategory {
+    /// Alphabetic characters
+    Letter,
+    /// Numeric characters
+    Digit,
+    /// Punctuation characters
+    Punct,
+    /// Anything else, such as symbols
+    Other,
+}
+
+impl CharCategory {
+    /// Returns the category of a character
+    pub fn of(c: char) -> Self {
+        if c.is_alphabetic() {
+            CharCategory::Letter
+        } else if c.is_numeric() {
+            CharCategory::Digit
+        } else if c.is_ascii_punctuation() {
+            CharCategory::Punct
+        } else {
+            CharCategory::Other
+        }
+    }
+}
+
 /// Configuration options for fuzzy search pattern generation
+///
+/// Only `search_term` is required when deserializing; missing fields get
+/// the builder defaults, as in [`Default`].
 #[derive(Debug, Clone, TypedBuilder)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(remote = "Self", default)
+)]
 #[builder(doc)]
 pub struct FuzzyConfig {
     /// Search term to create pattern for
@@ -87,6 +375,10 @@ pub struct FuzzyConfig {
 
     /// Required character ratio for longer words (0.0 to 1.0)
     #[builder(default = 0.5, setter(transform = |v: f32| v.clamp(0.0, 1.0)))]
+    #[cfg_attr(
+        feature = "serde",
+        serde(deserialize_with = "deserialize_unit_interval")
+    )]
     required_char_ratio: f32,
 
     /// Whether to enable case-sensitive matching
@@ -96,210 +388,4216 @@ pub struct FuzzyConfig {
     /// Maximum allowed character gap
     #[builder(default = 10)]
     max_char_gap: usize,
-}
 
-impl FuzzyConfig {
-    /// Creates a pattern based on the configuration
-    pub fn build_pattern(&self) -> Result<String, FuzzyError> {
-        create_fuzzy_pattern(&self.search_term, self)
-    }
+    /// Whether capitalized query words (likely proper nouns) require all characters
+    #[builder(default = false)]
+    strict_proper_nouns: bool,
 
-    /// Creates and compiles a regex based on the configuration
-    pub fn compile(&self) -> Result<fancy_regex::Regex, FuzzyError> {
-        let pattern = self.build_pattern()?;
-        Ok(fancy_regex::Regex::new(&pattern)?)
-    }
-}
+    /// Whether characters also match their leetspeak substitutes
+    ///
+    /// | Letter | Substitutes |
+    /// | ------ | ----------- |
+    /// | a      | `4`, `@`    |
+    /// | e      | `3`         |
+    /// | i      | `1`         |
+    /// | l      | `1`         |
+    /// | o      | `0`         |
+    /// | s      | `5`         |
+    /// | t      | `7`         |
+    ///
+    /// Substitutes in the query match their letters as well, so "1337" matches "leet".
+    #[builder(default = false)]
+    leet: bool,
 
-/// Creates a fuzzy search pattern with custom configuration
-fn create_fuzzy_pattern(search_term: &str, config: &FuzzyConfig) -> Result<String, FuzzyError> {
-    // Validate search term
-    if search_term.trim().is_empty() {
-        error!("Empty search term provided");
-        return Err(FuzzyError::EmptyPattern);
-    }
+    /// Whether gaps before optional characters are only allowed when the character is present
+    #[builder(default = false)]
+    strict_optional_gaps: bool,
 
-    // Split search term into words
-    let words: Vec<_> = search_term
-        .split_whitespace()
-        .filter(|w| !w.is_empty())
-        .collect();
+    /// Maximum gap around characters of a given category, overriding `max_char_gap`
+    #[builder(default)]
+    gap_by_category: HashMap<CharCategory, usize>,
 
-    if words.is_empty() {
-        error!("No valid words found in search term");
-        return Err(FuzzyError::EmptyPattern);
-    }
+    /// Maximum number of whole words allowed between consecutive query words
+    #[builder(default, setter(strip_option))]
+    max_words_between: Option<usize>,
 
-    // Check minimum word length requirement
-    if words
-        .iter()
-        .any(|w| w.chars().count() < config.min_word_length)
-    {
-        warn!(
-            "Words shorter than minimum length {}: {:?}",
-            config.min_word_length, words
-        );
-    }
+    /// Whether multi-word terms match regardless of the order of their words
+    ///
+    /// Each word is then asserted by its own lookahead from the start of the
+    /// haystack, as in `^(?=.*?hello)(?=.*?world)`, instead of joining the
+    /// words into one ordered sequence.
+    #[builder(default = false)]
+    ignore_word_order: bool,
 
-    // Split on whitespace but preserve punctuation
-    let words: Vec<String> = words
-        .into_iter()
-        .map(|word| {
-            if word.chars().any(|c| c.is_ascii_punctuation()) {
-                // For words with punctuation, create a pattern that allows matching with or without the punctuation
-                let parts: Vec<String> = word
-                    .split(|c: char| c.is_ascii_punctuation())
-                    .filter(|s| !s.is_empty())
-                    .map(|part| create_word_pattern(part, config))
-                    .collect();
-                parts.join("[\\s\\p{Z}\\p{C}]*")
-            } else {
-                create_word_pattern(word, config)
-            }
-        })
-        .collect();
+    /// Whether each query word must match at its own haystack position when word order is ignored
+    ///
+    /// This is verified after the regex matches, so it only applies through
+    /// [`CompiledFuzzy`] and the config's matching methods, not [`FuzzyConfig::compile`].
+    #[builder(default = false)]
+    distinct_word_positions: bool,
 
-    let case_flag = if !config.case_sensitive { "(?i)" } else { "" };
-    // For multiple words, require all words to be present with flexible whitespace
-    if words.len() > 1 {
-        Ok(format!(
-            "{}(?s).*?{}.*?",
-            case_flag,
-            words.join("[\\s\\p{Z}\\p{C}]+.*?")
-        ))
-    } else {
-        Ok(format!("{}(?s).*?{}.*?", case_flag, words[0]))
-    }
-}
+    /// Total number of edits tolerated per word, replacing the gap and ratio settings
+    ///
+    /// A budget of `n` allows up to `n` characters between any two query
+    /// characters (as `max_char_gap(n)` would) and lets up to `n` trailing
+    /// characters of each word go missing, keeping at least the first one.
+    /// A budget of 0 therefore requires an exact match.
+    #[builder(default, setter(strip_option))]
+    fuzziness_budget: Option<u32>,
 
-/// Creates a pattern for a single word
-fn create_word_pattern(word: &str, config: &FuzzyConfig) -> String {
-    debug!("Creating pattern for word: {}", word);
-    debug!(
-        "Config: max_char_gap={}, min_word_length={}, required_char_ratio={}",
-        config.max_char_gap, config.min_word_length, config.required_char_ratio
-    );
+    /// Whether case-insensitive ASCII terms use character classes instead of the `(?i)` flag
+    #[builder(default = false)]
+    auto_case_strategy: bool,
 
-    // Special handling for single character inputs
-    if word.chars().count() == 1 {
-        let char_pattern = fancy_regex::escape(word);
-        debug!("Single character pattern: {}", char_pattern);
-        return format!("(?:[^\\s]*?{}[^\\s]*?)", char_pattern);
-    }
+    /// Maximum length in bytes of a generated pattern before it is considered unsafe to compile
+    #[builder(default, setter(strip_option))]
+    max_pattern_len: Option<usize>,
 
-    let chars: Vec<_> = word
-        .chars()
-        .map(|c| {
-            let c_str = c.to_string();
-            let escaped = fancy_regex::escape(&c_str);
-            if c.is_ascii_punctuation() || c.is_ascii_digit() || !c.is_ascii() {
-                debug!("Special character '{}' escaped as: {}", c, escaped);
-                format!("(?:{})?", escaped)
-            } else if config.case_sensitive {
-                debug!("Case-sensitive character '{}' escaped as: {}", c, escaped);
-                escaped.into_owned()
-            } else {
-                debug!(
-                    "Case-insensitive character '{}' pattern: [{}{}]",
-                    c,
-                    c.to_lowercase(),
-                    c.to_uppercase()
-                );
-                let lower: String = c.to_lowercase().collect();
-                let upper: String = c.to_uppercase().collect();
-                format!("[{}{}]", lower, upper)
-            }
-        })
-        .collect();
-    debug!("Processed chars: {:?}", chars);
+    /// Whether oversized patterns fall back to edit distance matching instead of failing
+    ///
+    /// The fallback aligns the whole term against the haystack and tolerates as
+    /// many edits as the pattern has optional characters.
+    #[builder(default = false)]
+    streaming_fallback: bool,
 
-    // Create gap patterns based on configuration
-    let between_pattern = if config.max_char_gap > 0 {
-        // When max_char_gap is set, allow any characters within the limit
-        if config.max_char_gap > 10 {
-            // For large gaps, allow any characters including spaces
-            debug!(
-                "Using large gap pattern with max_char_gap={}",
-                config.max_char_gap
-            );
-            format!(".{{0,{}}}", config.max_char_gap)
-        } else {
-            // For small gaps, only allow non-space characters
-            debug!(
-                "Using small gap pattern with max_char_gap={}",
-                config.max_char_gap
-            );
-            format!("[^\\s]{{0,{}}}", config.max_char_gap)
-        }
-    } else {
-        // When max_char_gap is 0, don't allow any characters between
-        debug!("Using zero gap pattern");
-        "".to_string()
-    };
+    /// Case sensitivity for each word of the term, overriding `case_sensitive`
+    ///
+    /// Ignored unless there is exactly one entry per whitespace-separated word.
+    #[builder(default, setter(strip_option))]
+    per_word_case_sensitive: Option<Vec<bool>>,
 
-    debug!("Between pattern: {}", between_pattern);
+    /// Whether matchers skip haystacks too short to possibly match before running the regex
+    #[builder(default = false)]
+    length_prefilter: bool,
 
-    // For high required_char_ratio, enforce stricter matching but still allow some flexibility
-    let char_pattern = if config.required_char_ratio > 0.9 {
-        debug!(
-            "Using strict pattern with required_char_ratio={}",
-            config.required_char_ratio
-        );
-        // Require all characters with optional gaps
-        let mut pattern = String::new();
-        for (i, c) in chars.iter().enumerate() {
-            if i > 0 {
-                pattern.push_str(&between_pattern);
-            }
-            pattern.push_str(c);
-        }
-        pattern
-    } else {
-        // Allow flexible matching based on word length and required ratio
-        let required_chars = (chars.len() as f32 * config.required_char_ratio).ceil() as usize;
-        debug!(
-            "Using flexible pattern with required_char_ratio={}, required_chars={}",
-            config.required_char_ratio, required_chars
-        );
-        let (required, optional) = chars.split_at(required_chars);
+    /// Whether each word matches any haystack word that is a rearrangement of its characters
+    #[builder(default = false)]
+    anagram: bool,
 
-        let mut pattern = String::new();
-        // Add required characters with flexible gaps
-        for (i, c) in required.iter().enumerate() {
-            if i > 0 {
-                pattern.push_str(&between_pattern);
-            }
-            pattern.push_str(c);
+    /// Whether `[...]` bracket expressions in the term are used as regex character classes
+    ///
+    /// Each bracket expression counts as one character, so "gr[ae]y" matches
+    /// both "gray" and "grey". Invalid classes make pattern building fail.
+    #[builder(default = false)]
+    char_classes: bool,
+
+    /// Share of the score (0.0 to 1.0) reserved for matching words in query order
+    ///
+    /// When word order is ignored, the score is multiplied by
+    /// `1 - order_bonus * (1 - m)`, where `m` is the fraction of adjacent query
+    /// words found in ascending order, so in-order matches rank higher.
+    #[builder(default = 0.0, setter(transform = |v: f32| v.clamp(0.0, 1.0)))]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, deserialize_with = "deserialize_unit_interval")
+    )]
+    order_bonus: f32,
+
+    /// Whether numeric tokens match their digits regardless of phone formatting
+    ///
+    /// Dashes, dots, parentheses and `+` are ignored within a term word, and
+    /// spaces too in the haystack, so "5551234" matches "(555) 123-4".
+    #[builder(default = false)]
+    phone_mode: bool,
+
+    /// Whether quoted sections of the term are matched exactly
+    ///
+    /// In `fuzzy "exact phrase" words` the phrase must appear as written, up
+    /// to whitespace, while the other words stay fuzzy. Unbalanced quotes are
+    /// ignored.
+    #[builder(default = false)]
+    quoted_phrases: bool,
+
+    /// How `required_char_ratio` is rounded to a number of required characters
+    ///
+    /// At least one and at most all characters of a word are always required.
+    #[builder(default)]
+    ratio_rounding: RatioRounding,
+
+    /// Whether ordered multi-word terms report the shortest match instead of the first
+    ///
+    /// The match is retried after every later start position, so this costs
+    /// up to one regex search per character of the haystack.
+    #[builder(default = false)]
+    tightest_span: bool,
+
+    /// Maximum characters of each word used to build the pattern
+    ///
+    /// Longer words are truncated, so they match like a prefix of the word.
+    /// This bounds the pattern size for pathologically long words.
+    #[builder(default, setter(strip_option))]
+    max_word_chars: Option<usize>,
+
+    /// Whether an all-caps term is matched case-insensitively despite `case_sensitive`
+    ///
+    /// A term whose letters are all uppercase, such as "HELLO", was most likely
+    /// typed with caps lock on, so it is treated as if `case_sensitive` were
+    /// false. Terms with any lowercase letter keep the configured sensitivity.
+    #[builder(default = false)]
+    smart_caps: bool,
+
+    /// Whether camelCase words are split into separately matched parts
+    ///
+    /// Runs of capitals stay together, so "JSONParser" matches as "JSON" and
+    /// "Parser", which may then be separated by whitespace or punctuation in
+    /// the haystack.
+    #[builder(default = false)]
+    camel_case: bool,
+
+    /// Custom normalization applied to the search term before building patterns
+    ///
+    /// Haystacks are matched as given, so apply the same normalization to
+    /// them with [`FuzzyConfig::normalize`] before matching.
+    #[builder(
+        default,
+        setter(transform = |f: Box<dyn Fn(&str) -> String + Send + Sync>| Some(Normalizer(Arc::from(f))))
+    )]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    normalizer: Option<Normalizer>,
+
+    /// Unicode normalization form applied to the search term before building patterns
+    ///
+    /// Applied before any `normalizer`. Haystacks must be normalized the same
+    /// way, for example with [`FuzzyConfig::normalize`], or precomposed and
+    /// decomposed forms of the same text won't match each other.
+    #[cfg(feature = "unicode-normalization")]
+    #[builder(default, setter(strip_option))]
+    normalize: Option<Normalization>,
+
+    /// Whether words match in any order, with in-order matches scoring higher
+    ///
+    /// This matches like `ignore_word_order` and scores like `order_bonus`,
+    /// using [`SOFT_ORDER_BONUS`] unless an `order_bonus` is set.
+    #[builder(default = false)]
+    soft_order: bool,
+
+    /// Whether the pattern matches within single lines, for grep-like tools
+    ///
+    /// The pattern is anchored at line starts with `(?m)`, leaves out `(?s)`
+    /// and only allows spaces and tabs between words, so a match never spans
+    /// lines and the regex can be run line by line.
+    #[builder(default = false)]
+    grep_mode: bool,
+
+    /// Whether matches must start a sentence
+    ///
+    /// A sentence starts at the start of the text (or line, in grep mode) or
+    /// after `.`, `!` or `?`. Ignored when word order is ignored.
+    #[builder(default = false)]
+    sentence_start: bool,
+
+    /// Whether emoji sequences match as single units, regardless of skin tone
+    ///
+    /// An emoji in the term, including ZWJ sequences, counts as one letter
+    /// instead of an optional symbol, and matches its base form with or
+    /// without skin tone modifiers.
+    #[builder(default = false)]
+    emoji_aware: bool,
+
+    /// Whether punctuation inside a word stays an optional character in place
+    ///
+    /// By default "foo.bar" is split into "foo" and "bar", which may then be
+    /// separated by whitespace or punctuation. With this set the word is kept
+    /// whole, so it matches "foo.bar" or "foobar" but not "foo bar".
+    #[builder(default = false)]
+    preserve_punctuation_position: bool,
+
+    /// Whether ranking lists repeated candidate strings only once
+    ///
+    /// Identical candidates always score the same, so the first index is kept.
+    #[builder(default = false)]
+    dedup_results: bool,
+
+    /// Relative tolerance for numeric words, such as 0.05 for 5%
+    ///
+    /// A numeric word like "$99.99" is matched by any number in the pattern,
+    /// and matches are then post-verified by parsing the haystack numbers, so
+    /// "$100" matches with a tolerance of 0.01 but not with 0.0001.
+    #[builder(default, setter(strip_option))]
+    numeric_tolerance: Option<f32>,
+
+    /// Abbreviations mapped to their expansions, such as "db" to "database"
+    ///
+    /// A query word matching either form, ignoring case, also matches the
+    /// other form literally.
+    #[builder(default)]
+    abbreviations: HashMap<String, String>,
+
+    /// Inverse document frequencies of query words, keyed in lowercase
+    ///
+    /// When set, the score is the average of each word's own score, weighted
+    /// by its IDF, so matching rare words closely counts for more.
+    #[builder(default)]
+    idf_weights: HashMap<String, f32>,
+
+    /// Whether the haystack only needs to contain each character of the term
+    ///
+    /// Each character must appear at least as often as in the term, in any
+    /// order and anywhere in the haystack. This is the loosest mode, useful as
+    /// a coarse prefilter.
+    #[builder(default = false)]
+    bag_of_chars: bool,
+
+    /// Whether patterns that match every haystack are rejected
+    ///
+    /// Terms made only of optional characters, such as "123" or "!!!", produce
+    /// such patterns; building or compiling them then fails with
+    /// [`FuzzyError::TrivialPattern`].
+    #[builder(default = false)]
+    reject_trivial: bool,
+
+    /// Whether adjacent required characters may appear swapped, as in "teh" for "the"
+    ///
+    /// Only the first [`MAX_TRANSPOSITION_PAIRS`] pairs of each word can be
+    /// swapped, one pair at a time, to bound the pattern size.
+    #[builder(default = false)]
+    transposition_tolerance: bool,
+
+    /// Where each query word must sit within a haystack word
+    #[builder(default)]
+    word_anchoring: WordAnchor,
+
+    /// Maximum bytes a match may span, from its first to its last matched character
+    ///
+    /// Wider matches are rejected even if every gap is within `max_char_gap`.
+    /// Ordered terms fall back to the tightest match in the haystack.
+    #[builder(default, setter(strip_option))]
+    max_match_span: Option<usize>,
+
+    /// Where the match must sit within the haystack
+    ///
+    /// In `grep_mode` the anchors apply to each line. Unordered terms must
+    /// have one of their words at the anchored end, and `bag_of_chars`
+    /// ignores the anchor.
+    #[builder(default)]
+    anchor: Anchor,
+
+    /// Whether words shorter than `min_word_length` are optional in multi-word terms
+    ///
+    /// Such words still count toward the score when present. If every word
+    /// is short, all of them stay required.
+    #[builder(default = false)]
+    short_words_optional: bool,
+
+    /// Whether the second of a doubled character may be missing, as in "adress"
+    ///
+    /// Applies even to characters that are otherwise required, but not to
+    /// words below `min_word_length`, which match exactly.
+    #[builder(default = false)]
+    tolerate_missing_doubles: bool,
+
+    /// Whether the gap between characters of a word may include whitespace
+    ///
+    /// When unset, gaps above 10 allow whitespace and smaller gaps don't.
+    /// That threshold is deprecated: set this explicitly to keep the same
+    /// behavior whatever the gap, `Some(true)` for gaps above 10 and
+    /// `Some(false)` otherwise.
+    #[builder(default, setter(strip_option))]
+    gap_allows_whitespace: Option<bool>,
+
+    /// Keyboard layout whose adjacent keys also match each letter
+    ///
+    /// Only widens the character class of each letter, as in `[aAsSqQwWzZ]`
+    /// for "a", so typos hitting a neighboring key still match. Gaps are
+    /// unaffected.
+    #[builder(default, setter(strip_option))]
+    keyboard_layout: Option<KeyboardLayout>,
+
+    /// Maximum node count of the generated pattern, as reported by `estimated_complexity`
+    ///
+    /// Building or compiling a more complex pattern fails with
+    /// [`FuzzyError::InvalidPattern`], which protects servers from search
+    /// terms producing patterns that are slow to match.
+    #[builder(default, setter(strip_option))]
+    max_pattern_complexity: Option<usize>,
+
+    /// Whether every character of each word is required
+    ///
+    /// Acts like a `required_char_ratio` of 1.0 whatever the ratio or
+    /// `fuzziness_budget`, so no optional tail is generated and "programming"
+    /// no longer matches "program". Gaps between characters are still allowed.
+    #[builder(default = false)]
+    require_full_word: bool,
+
+    /// How query characters are folded when matching case-insensitively
+    ///
+    /// [`CaseFold::Full`] lets characters such as "ß" and ligatures such as
+    /// "ﬁ" in the term match their expansions, and the Turkish "İ" and "ı"
+    /// match "i" and "I". Only the term is expanded, so "ss" in the term
+    /// doesn't match "ß".
+    #[builder(default)]
+    case_fold: CaseFold,
+
+    /// Raw regex fragment matched between the characters of a word
+    ///
+    /// Replaces the gap derived from `max_char_gap`, `gap_by_category` and
+    /// `gap_allows_whitespace`. The fragment must compile on its own and may
+    /// not close groups it didn't open, alternate with `|` outside a group or
+    /// capture, or building the pattern fails with [`FuzzyError::InvalidPattern`].
+    /// Use `(?:...)` for groups.
+    #[builder(default, setter(strip_option, into))]
+    custom_gap_pattern: Option<String>,
+
+    /// Whether Latin letters match their accented forms, so "cafe" matches "café"
+    ///
+    /// Accents are stripped from the term, including combining marks, and
+    /// each letter with accented forms matches a class of them, such as
+    /// `[eEéÉèÈ...]` for "e". Case-sensitive terms only get forms of the same case.
+    #[builder(default = false)]
+    ignore_diacritics: bool,
+
+    /// Whether ASCII digits must be present, as in part numbers like "A1234"
+    ///
+    /// Digits are otherwise optional, so "test123" matches "test". When set,
+    /// digits are matched literally and every character up to the last digit
+    /// of a word is required, whatever the `required_char_ratio`.
+    #[builder(default = false)]
+    digits_required: bool,
+
+    /// Number of trailing characters of each word allowed to go missing
+    ///
+    /// Exactly `len - max_typos` characters of a word are required, keeping at
+    /// least the first one. Takes precedence over `required_char_ratio` and
+    /// the ratio part of `fuzziness_budget`; the gap settings still apply.
+    #[builder(default, setter(strip_option))]
+    max_typos: Option<usize>,
+
+    /// Whether matches must stay within one line
+    ///
+    /// Leaves out `(?s)` and keeps line breaks out of the space between
+    /// words, so a multi-word term no longer matches across paragraphs.
+    /// Unlike `grep_mode`, the haystack is still searched as a whole.
+    #[builder(default = false)]
+    single_line: bool,
+}
+
+/// Deserializes a ratio clamped to 0.0..=1.0, as the builder setters do
+#[cfg(feature = "serde")]
+fn deserialize_unit_interval<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<f32, D::Error> {
+    let value = <f32 as serde::Deserialize>::deserialize(deserializer)?;
+    Ok(value.clamp(0.0, 1.0))
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for FuzzyConfig {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        FuzzyConfig::serialize(self, serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for FuzzyConfig {
+    /// Requires `search_term`, which the derived container default would fill with ""
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        struct WithTerm {
+            search_term: String,
+            #[serde(flatten)]
+            rest: Rest,
         }
 
-        // Add optional characters
-        if !optional.is_empty() {
-            debug!("Adding {} optional characters", optional.len());
-            pattern.push_str("(?:");
-            for (i, c) in optional.iter().enumerate() {
-                if i > 0 {
-                    pattern.push_str(&between_pattern);
-                }
-                pattern.push_str(&format!("{}?", c));
+        /// The other fields, deserialized with the derived implementation
+        struct Rest(FuzzyConfig);
+
+        impl<'de> serde::Deserialize<'de> for Rest {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                FuzzyConfig::deserialize(deserializer).map(Rest)
             }
-            pattern.push_str(")?");
         }
-        pattern
-    };
 
-    // Create the final pattern with appropriate word boundaries
-    let final_pattern = format!("(?:{})", char_pattern);
-    debug!("Final word pattern: {}", final_pattern);
-    final_pattern
+        let WithTerm {
+            search_term,
+            rest: Rest(config),
+        } = WithTerm::deserialize(deserializer)?;
+        Ok(FuzzyConfig {
+            search_term,
+            ..config
+        })
+    }
 }
 
-/// Simplified function for quick fuzzy pattern generation with default settings
-pub fn fuzzy_search_pattern(search_term: &str) -> String {
-    FuzzyConfig::builder()
-        .search_term(search_term)
-        .build()
-        .build_pattern()
-        .unwrap_or_else(|_| "".to_string())
+impl Default for FuzzyConfig {
+    /// Creates a config with the builder's defaults and an empty search term
+    ///
+    /// Set a term with [`with_term`](Self::with_term) before matching, since
+    /// an empty term fails with [`FuzzyError::EmptyPattern`].
+    fn default() -> Self {
+        Self::new("")
+    }
+}
+
+impl FuzzyConfig {
+    /// Creates a config for the term with the builder's defaults
+    pub fn new(term: impl Into<String>) -> Self {
+        Self::builder().search_term(term).build()
+    }
+
+    /// Creates a pre-tuned config with an empty term, to be set with [`with_term`](Self::with_term)
+    ///
+    /// See [`Preset`] for the values each preset uses. Options a preset does not
+    /// mention keep the builder's defaults.
+    pub fn preset(preset: Preset) -> Self {
+        let builder = Self::builder().search_term("");
+        match preset {
+            Preset::Strict => builder.required_char_ratio(1.0).max_char_gap(0).build(),
+            Preset::Typo => builder.required_char_ratio(0.8).max_char_gap(1).build(),
+            Preset::Loose => builder.required_char_ratio(0.4).max_char_gap(10).build(),
+            Preset::Autocomplete => builder
+                .required_char_ratio(0.6)
+                .anchor(Anchor::Start)
+                .build(),
+        }
+    }
+
+    /// Returns a copy of this config searching for a different term
+    pub fn with_term(&self, term: impl Into<String>) -> FuzzyConfig {
+        FuzzyConfig {
+            search_term: term.into(),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the gap allowed between characters, derived from the fuzziness budget if set
+    fn effective_max_char_gap(&self) -> usize {
+        self.fuzziness_budget
+            .map_or(self.max_char_gap, |budget| budget as usize)
+    }
+
+    /// Applies the configured Unicode normalization and normalizer to the text, if any
+    ///
+    /// With `ignore_diacritics`, accents are then stripped from Latin letters.
+    pub fn normalize<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        #[cfg(feature = "unicode-normalization")]
+        let text: Cow<'a, str> = match self.normalize {
+            Some(form) => Cow::Owned(form.apply(text)),
+            None => Cow::Borrowed(text),
+        };
+        #[cfg(not(feature = "unicode-normalization"))]
+        let text = Cow::Borrowed(text);
+        let text = match &self.normalizer {
+            Some(Normalizer(f)) => Cow::Owned(f(&text)),
+            None => text,
+        };
+        if !self.ignore_diacritics {
+            return text;
+        }
+        Cow::Owned(
+            text.chars()
+                .filter(|&c| !is_combining_mark(c))
+                .map(fold_diacritic)
+                .collect(),
+        )
+    }
+
+    /// Checks whether `normalize` changes text at all
+    fn has_normalization(&self) -> bool {
+        if self.ignore_diacritics {
+            return true;
+        }
+        #[cfg(feature = "unicode-normalization")]
+        if self.normalize.is_some() {
+            return true;
+        }
+        self.normalizer.is_some()
+    }
+
+    /// Returns the search term as the matcher sees it, for use as an index key
+    ///
+    /// Applies [`normalize`](Self::normalize), including any diacritic
+    /// folding, joins the words and phrases with single spaces, and lowercases
+    /// every word matched case-insensitively, so two terms with the same
+    /// canonical query match the same haystacks.
+    pub fn canonical_query(&self) -> String {
+        let config = self.normalized();
+        let tokens = config.query_tokens();
+        let per_word_case = config.per_word_case(tokens.len());
+        tokens
+            .iter()
+            .enumerate()
+            .map(|(i, token)| {
+                let text = token
+                    .text()
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let case_sensitive =
+                    per_word_case.map_or(config.is_case_sensitive(), |cases| cases[i]);
+                if case_sensitive {
+                    text
+                } else {
+                    text.to_lowercase()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Returns this config with the normalizer applied to the search term
+    ///
+    /// The returned config has no normalization, so it is never applied twice.
+    fn normalized(&self) -> Cow<'_, FuzzyConfig> {
+        if !self.has_normalization() {
+            return Cow::Borrowed(self);
+        }
+        let mut config = self.clone();
+        config.search_term = self.normalize(&self.search_term).into_owned();
+        config.normalizer = None;
+        #[cfg(feature = "unicode-normalization")]
+        {
+            config.normalize = None;
+        }
+        debug!("Normalized search term: {}", config.search_term);
+        Cow::Owned(config)
+    }
+
+    /// Returns the inline flag letting `.` match newlines, unless matching per line
+    fn dotall_flag(&self) -> &'static str {
+        if self.grep_mode || self.single_line {
+            ""
+        } else {
+            "(?s)"
+        }
+    }
+
+    /// Returns the assertion that lookaheads start from, any line start with `single_line`
+    fn line_start(&self) -> &'static str {
+        if self.single_line {
+            "(?:^|(?<=\\n))"
+        } else {
+            "^"
+        }
+    }
+
+    /// Returns the class of whitespace allowed between words
+    ///
+    /// Grep mode and `single_line` exclude line breaks so matches never span lines.
+    fn space_class(&self) -> &'static str {
+        if self.grep_mode || self.single_line {
+            "[^\\S\\r\\n]"
+        } else {
+            "[\\s\\p{Z}\\p{C}]"
+        }
+    }
+
+    /// Returns the expansion of an abbreviation, or the abbreviation of an expansion
+    fn abbreviation_for(&self, word: &str) -> Option<&str> {
+        let word = word.to_lowercase();
+        self.abbreviations.iter().find_map(|(abbr, expansion)| {
+            if abbr.to_lowercase() == word {
+                Some(expansion.as_str())
+            } else if expansion.to_lowercase() == word {
+                Some(abbr.as_str())
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Returns whether query words may match in any order
+    fn unordered(&self) -> bool {
+        self.ignore_word_order || self.soft_order
+    }
+
+    /// Returns the order bonus, defaulting to [`SOFT_ORDER_BONUS`] under `soft_order`
+    fn effective_order_bonus(&self) -> f32 {
+        if self.soft_order && self.order_bonus == 0.0 {
+            SOFT_ORDER_BONUS
+        } else {
+            self.order_bonus
+        }
+    }
+
+    /// Returns whether matching is case-sensitive, taking `smart_caps` into account
+    fn is_case_sensitive(&self) -> bool {
+        self.case_sensitive && !(self.smart_caps && is_all_caps(&self.search_term))
+    }
+
+    /// Returns the inline flag making the whole pattern case-insensitive, if needed
+    ///
+    /// With `auto_case_strategy`, ASCII terms rely on the `[aA]` classes
+    /// emitted per character and skip the flag; other terms keep it so that
+    /// Unicode case folding stays correct.
+    fn case_flag(&self) -> &'static str {
+        let word_count = self.query_tokens().len();
+        if self.is_case_sensitive()
+            || self.auto_case_strategy && self.search_term.is_ascii()
+            || self.per_word_case(word_count).is_some()
+        {
+            ""
+        } else {
+            "(?i)"
+        }
+    }
+
+    /// Splits the term into words and, with `quoted_phrases`, quoted phrases
+    fn query_tokens(&self) -> Vec<QueryToken<'_>> {
+        parse_query(&self.search_term, self.quoted_phrases)
+    }
+
+    /// Splits text, parsed as a term, into the units an exact match consists of
+    ///
+    /// Quotes are left out and words are separated by single spaces. Bracket
+    /// expressions under `char_classes` are one unit, and `phone_mode` drops
+    /// phone formatting within words.
+    fn match_units<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        let mut units = Vec::new();
+        for (i, token) in parse_query(text, self.quoted_phrases)
+            .into_iter()
+            .enumerate()
+        {
+            if i > 0 {
+                units.push(" ");
+            }
+            match token {
+                QueryToken::Word(word) => units.extend(
+                    split_units(self.truncate_word(word), self)
+                        .into_iter()
+                        .filter(|unit| !(self.phone_mode && is_phone_formatting(unit))),
+                ),
+                QueryToken::Phrase(phrase) => units.extend(
+                    phrase
+                        .char_indices()
+                        .map(|(i, c)| &phrase[i..i + c.len_utf8()]),
+                ),
+            }
+        }
+        units
+    }
+
+    /// Scores a matched region against a term
+    ///
+    /// The score compares the character counts of the term's
+    /// [`match_units`](Self::match_units) and of the span as `min / max`, so
+    /// an exact match scores 1.0 and gaps or missing characters lower it.
+    /// Whitespace in the span, and phone formatting under `phone_mode`, isn't counted.
+    fn span_score(&self, term: &str, span: &str) -> f32 {
+        let term_len: usize = self
+            .match_units(term)
+            .iter()
+            .map(|unit| {
+                if is_class_unit(unit) {
+                    1
+                } else {
+                    unit.chars().filter(|c| !c.is_whitespace()).count()
+                }
+            })
+            .sum();
+        let span_len = span
+            .chars()
+            .filter(|&c| !(c.is_whitespace() || self.phone_mode && PHONE_FORMATTING.contains(&c)))
+            .count();
+        let longest = term_len.max(span_len);
+        if longest == 0 {
+            return 0.0;
+        }
+        term_len.min(span_len) as f32 / longest as f32
+    }
+
+    /// Flags the tokens that `short_words_optional` makes optional
+    fn optional_words(&self, tokens: &[QueryToken]) -> Vec<bool> {
+        let optional: Vec<bool> = tokens
+            .iter()
+            .map(|token| {
+                self.short_words_optional
+                    && matches!(token, QueryToken::Word(word) if word.chars().count() < self.min_word_length)
+            })
+            .collect();
+        if optional.iter().all(|&o| o) {
+            return vec![false; tokens.len()];
+        }
+        optional
+    }
+
+    /// Shortens a word to `max_word_chars` characters, if set
+    fn truncate_word<'a>(&self, word: &'a str) -> &'a str {
+        let Some(max) = self.max_word_chars else {
+            return word;
+        };
+        match word.char_indices().nth(max) {
+            Some((end, _)) => {
+                debug!("Truncating word '{}' to {} characters", word, max);
+                &word[..end]
+            }
+            None => word,
+        }
+    }
+
+    /// Compiles a standalone regex for each word of the term
+    fn word_regexes(&self) -> Result<Vec<fancy_regex::Regex>, FuzzyError> {
+        let case_flag = self.case_flag();
+        create_word_patterns(&self.search_term, self)?
+            .iter()
+            .map(|word| {
+                fancy_regex::Regex::new(&format!("{}{}{}", case_flag, self.dotall_flag(), word))
+                    .map_err(FuzzyError::from)
+            })
+            .collect()
+    }
+
+    /// Returns the per-word case settings if there is exactly one for each word
+    fn per_word_case(&self, word_count: usize) -> Option<&[bool]> {
+        self.per_word_case_sensitive
+            .as_deref()
+            .filter(|cases| cases.len() == word_count)
+    }
+
+    /// Checks the configuration for values that would produce a pathological pattern
+    ///
+    /// Fails with [`FuzzyError::EmptyPattern`] for a blank search term, and
+    /// with [`FuzzyError::InvalidPattern`] for ratios outside 0.0 to 1.0, gaps
+    /// above [`MAX_CHAR_GAP`], including those a `fuzziness_budget` derives,
+    /// a malformed `custom_gap_pattern` or a negative numeric tolerance.
+    /// Building or compiling a pattern validates first.
+    pub fn validate(&self) -> Result<(), FuzzyError> {
+        let invalid = |message: String| {
+            error!("Invalid configuration: {}", message);
+            Err(FuzzyError::InvalidPattern(message))
+        };
+        if self.search_term.trim().is_empty() {
+            error!("Empty search term provided");
+            return Err(FuzzyError::EmptyPattern);
+        }
+        for (name, ratio) in [
+            ("required_char_ratio", self.required_char_ratio),
+            ("order_bonus", self.order_bonus),
+        ] {
+            if !(0.0..=1.0).contains(&ratio) {
+                return invalid(format!(
+                    "{} must be between 0.0 and 1.0, got {}",
+                    name, ratio
+                ));
+            }
+        }
+        let largest_gap = self
+            .gap_by_category
+            .values()
+            .copied()
+            .chain(std::iter::once(self.effective_max_char_gap()))
+            .max()
+            .unwrap_or(0);
+        if largest_gap > MAX_CHAR_GAP {
+            return invalid(format!(
+                "character gaps are limited to {}, got {}",
+                MAX_CHAR_GAP, largest_gap
+            ));
+        }
+        if let Some(gap) = &self.custom_gap_pattern {
+            if let Err(problem) = check_gap_structure(gap) {
+                return invalid(format!("custom_gap_pattern {:?} {}", gap, problem));
+            }
+            if let Err(err) = fancy_regex::Regex::new(&format!("(?:{})", gap)) {
+                return invalid(format!(
+                    "custom_gap_pattern {:?} doesn't compile: {}",
+                    gap, err
+                ));
+            }
+        }
+        if let Some(tolerance) = self.numeric_tolerance {
+            if !(tolerance >= 0.0 && tolerance.is_finite()) {
+                return invalid(format!(
+                    "numeric_tolerance must be a non-negative number, got {}",
+                    tolerance
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates a pattern based on the configuration
+    pub fn build_pattern(&self) -> Result<String, FuzzyError> {
+        let pattern = self.unchecked_pattern()?;
+        self.check_trivial(&pattern)?;
+        self.check_complexity(&pattern)?;
+        Ok(pattern)
+    }
+
+    /// Creates a pattern matching haystacks that both configs match
+    ///
+    /// Each pattern goes in its own lookahead, `(?s)^(?=.*?(?:a))(?=.*?(?:b))`,
+    /// so the matches may sit anywhere and overlap. The case flags of the two
+    /// configs are scoped to their lookaheads rather than merged, so when they
+    /// differ the stricter one still applies to its own term.
+    pub fn and(&self, other: &FuzzyConfig) -> Result<String, FuzzyError> {
+        let pattern = format!(
+            "(?s)^(?=.*?(?:{}))(?=.*?(?:{}))",
+            self.build_pattern()?,
+            other.build_pattern()?
+        );
+        debug!("Combined pattern: {}", pattern);
+        Ok(pattern)
+    }
+
+    /// Returns the pattern of each word or phrase of the term, in order
+    ///
+    /// These are the pieces [`build_pattern`](Self::build_pattern) joins,
+    /// without the separators, surrounding context or leading case flag. Add
+    /// `(?i)` when recombining them unless the term is case-sensitive.
+    pub fn word_patterns(&self) -> Result<Vec<String>, FuzzyError> {
+        self.validate()?;
+        let config = self.normalized();
+        create_word_patterns(&config.search_term, &config)
+    }
+
+    /// Creates the pattern without the `reject_trivial` and complexity checks
+    fn unchecked_pattern(&self) -> Result<String, FuzzyError> {
+        self.validate()?;
+        let config = self.normalized();
+        create_fuzzy_pattern(&config.search_term, &config, PatternShape::Search)
+    }
+
+    /// Rejects patterns whose node count exceeds `max_pattern_complexity`
+    fn check_complexity(&self, pattern: &str) -> Result<(), FuzzyError> {
+        let Some(max) = self.max_pattern_complexity else {
+            return Ok(());
+        };
+        let complexity = pattern_complexity(pattern);
+        if complexity > max {
+            error!("Pattern complexity {} exceeds limit {}", complexity, max);
+            return Err(FuzzyError::InvalidPattern(format!(
+                "pattern complexity {} exceeds max_pattern_complexity {}",
+                complexity, max
+            )));
+        }
+        Ok(())
+    }
+
+    /// Returns the generated pattern in extended mode, annotated with comments
+    ///
+    /// Each word's part of the pattern is put on its own line after a
+    /// `# word: ...` comment, and the surrounding context and separators get
+    /// their own lines. Literal whitespace and `#`, which extended mode would
+    /// ignore or read as a comment, are written as hex escapes, so the result
+    /// compiles to an equivalent regex.
+    pub fn explain_pattern(&self) -> Result<String, FuzzyError> {
+        self.validate()?;
+        let config = self.normalized();
+        let pattern = create_fuzzy_pattern(&config.search_term, &config, PatternShape::Search)?;
+        let words = create_word_patterns(&config.search_term, &config)?;
+
+        let mut explained = String::from("(?x)\n");
+        let mut pos = 0;
+        for (token, word) in config.query_tokens().iter().zip(&words) {
+            let Some(offset) = pattern[pos..].find(word.as_str()) else {
+                continue;
+            };
+            let context = &pattern[pos..pos + offset];
+            if !context.is_empty() {
+                explained.push_str(&format!("{}\n", escape_extended(context)));
+            }
+            explained.push_str(&format!(
+                "# word: {}\n{}\n",
+                token.text(),
+                escape_extended(word)
+            ));
+            pos += offset + word.len();
+        }
+        if pos < pattern.len() {
+            explained.push_str(&format!("{}\n", escape_extended(&pattern[pos..])));
+        }
+        Ok(explained)
+    }
+
+    /// Describes in plain words how the term is matched
+    ///
+    /// Gives one clause per word, such as "Matching 'hello'
+    /// case-insensitively, requiring first 3 of 5 characters, allowing up to
+    /// 2 non-space characters between each", followed by the word order for
+    /// multi-word terms. Gap overrides by category aren't described.
+    pub fn describe(&self) -> String {
+        let config = self.normalized();
+        let tokens = config.query_tokens();
+        let per_word_case = config.per_word_case(tokens.len());
+        let optional = config.optional_words(&tokens);
+        let gap = config.effective_max_char_gap();
+        let gap = match &config.custom_gap_pattern {
+            Some(pattern) => format!("allowing '{}' between each", pattern),
+            None if gap == 0 => "allowing nothing between them".to_string(),
+            None => {
+                let kind = if config
+                    .gap_allows_whitespace
+                    .unwrap_or(gap > LEGACY_WHITESPACE_GAP)
+                {
+                    ""
+                } else {
+                    "non-space "
+                };
+                let plural = if gap == 1 { "" } else { "s" };
+                format!(
+                    "allowing up to {} {}character{} between each",
+                    gap, kind, plural
+                )
+            }
+        };
+        let clauses: Vec<String> = tokens
+            .iter()
+            .enumerate()
+            .map(|(i, token)| {
+                let case_sensitive =
+                    per_word_case.map_or(config.is_case_sensitive(), |cases| cases[i]);
+                let case = if case_sensitive {
+                    "case-sensitively"
+                } else {
+                    "case-insensitively"
+                };
+                let word = match token {
+                    QueryToken::Phrase(phrase) => {
+                        return format!("matching '{}' {} as an exact phrase", phrase, case)
+                    }
+                    QueryToken::Word(word) => config.truncate_word(word),
+                };
+                let optional = if optional[i] { " (optional)" } else { "" };
+                let len = word.chars().count();
+                if len < config.min_word_length {
+                    return format!("matching '{}'{} {} exactly", word, optional, case);
+                }
+                let analysis = analyze_word(word, &config);
+                let total = analysis.required.len() + analysis.optional.len();
+                let required = if analysis.optional.is_empty() {
+                    format!("requiring all {} characters", total)
+                } else {
+                    format!(
+                        "requiring first {} of {} characters",
+                        analysis.required_chars, total
+                    )
+                };
+                format!(
+                    "matching '{}'{} {}, {}, {}",
+                    word, optional, case, required, gap
+                )
+            })
+            .collect();
+        let mut description = clauses.join("; ");
+        if let Some(first) = description.get(..1) {
+            description.replace_range(..1, &first.to_uppercase());
+        }
+        if tokens.len() > 1 {
+            description.push_str(if config.unordered() {
+                "; words in any order"
+            } else {
+                "; words in order"
+            });
+        }
+        description
+    }
+
+    /// Checks whether a pattern is exactly what this configuration generates
+    ///
+    /// Useful to detect cached patterns that drifted from the current settings.
+    pub fn pattern_matches_config(&self, pattern: &str) -> bool {
+        self.build_pattern()
+            .is_ok_and(|generated| generated == pattern)
+    }
+
+    /// Creates and compiles a regex based on the configuration
+    ///
+    /// Fails with [`FuzzyError::InvalidPattern`] if the pattern exceeds
+    /// `max_pattern_len`, even when `streaming_fallback` is set.
+    pub fn compile(&self) -> Result<fancy_regex::Regex, FuzzyError> {
+        let pattern = self.build_pattern()?;
+        self.check_pattern_len(&pattern)?;
+        Ok(fancy_regex::Regex::new(&pattern)?)
+    }
+
+    /// Compiles the configuration once for matching many haystacks
+    ///
+    /// Prefer this over calling [`compile`](Self::compile) or the matching
+    /// methods of the config in a loop, since each of those compiles anew.
+    pub fn compiled(&self) -> Result<CompiledFuzzy, FuzzyError> {
+        CompiledFuzzy::new(self.clone())
+    }
+
+    /// Rejects patterns matching every haystack when `reject_trivial` is set
+    ///
+    /// Generated patterns search the whole haystack, so a pattern matching the
+    /// empty string matches anything.
+    fn check_trivial(&self, pattern: &str) -> Result<(), FuzzyError> {
+        if !self.reject_trivial {
+            return Ok(());
+        }
+        if fancy_regex::Regex::new(pattern)?.is_match("")? {
+            error!("Pattern matches everything: {}", pattern);
+            return Err(FuzzyError::TrivialPattern);
+        }
+        Ok(())
+    }
+
+    /// Rejects patterns longer than `max_pattern_len`
+    fn check_pattern_len(&self, pattern: &str) -> Result<(), FuzzyError> {
+        match self.max_pattern_len {
+            Some(max) if pattern.len() > max => {
+                error!("Pattern of {} bytes exceeds limit {}", pattern.len(), max);
+                Err(FuzzyError::InvalidPattern(format!(
+                    "pattern of {} bytes exceeds max_pattern_len {}",
+                    pattern.len(),
+                    max
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Lower bound on the number of characters any match must contain
+    ///
+    /// Only required ASCII letters are counted since digits, punctuation and
+    /// other characters are optional in the generated pattern. Ordered terms
+    /// also need a separator between words, while unordered words may overlap.
+    /// Words with a known abbreviation count the shorter of the two forms.
+    fn min_match_len(&self) -> usize {
+        if self.bag_of_chars {
+            return self
+                .search_term
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .count();
+        }
+        let tokens = self.query_tokens();
+        let optional = self.optional_words(&tokens);
+        let word_lens = tokens.into_iter().zip(optional).map(|(token, optional)| {
+            if optional {
+                return 0;
+            }
+            let word = match token {
+                QueryToken::Word(word) => self.truncate_word(word),
+                // Phrases match exactly, with at least one byte per character
+                QueryToken::Phrase(phrase) => return phrase.chars().count(),
+            };
+            let len = word_parts(word, self)
+                .into_iter()
+                .map(|part| {
+                    let (units, required) = split_word(part, self);
+                    units
+                        .iter()
+                        .take(required)
+                        .enumerate()
+                        .filter(|&(i, _)| !is_missable_double(&units, i, self))
+                        .map(|(_, unit)| unit)
+                        .filter(|unit| {
+                            is_class_unit(unit)
+                                || (self.digits_required && is_digit_unit(unit))
+                                || unit.chars().all(|c| c.is_ascii_alphabetic())
+                        })
+                        .count()
+                })
+                .sum::<usize>();
+            // The other form of an abbreviation matches literally and may be shorter
+            match self.abbreviation_for(word) {
+                Some(other) => len.min(other.chars().count()),
+                None => len,
+            }
+        });
+        if self.unordered() {
+            word_lens.max().unwrap_or(0)
+        } else {
+            let word_lens: Vec<usize> = word_lens.filter(|&len| len > 0).collect();
+            word_lens.iter().sum::<usize>() + word_lens.len().saturating_sub(1)
+        }
+    }
+
+    /// Number of edits the edit distance fallback tolerates
+    ///
+    /// This is the number of characters the generated pattern would let go
+    /// missing, summed over all words.
+    fn fallback_max_edits(&self) -> usize {
+        self.query_tokens()
+            .into_iter()
+            .map(|token| match token {
+                QueryToken::Word(word) => {
+                    let word = self.truncate_word(word);
+                    let len = word.chars().count();
+                    len - required_char_count(word, len, self)
+                }
+                QueryToken::Phrase(_) => 0,
+            })
+            .sum()
+    }
+
+    /// Checks whether the haystack matches, see [`CompiledFuzzy::is_match`]
+    pub fn is_match(&self, haystack: &str) -> Result<bool, FuzzyError> {
+        Ok(CompiledFuzzy::new(self.clone())?.is_match(haystack))
+    }
+
+    /// Checks whether a possibly invalid UTF-8 haystack matches, see [`CompiledFuzzy::is_match_bytes`]
+    pub fn is_match_bytes(&self, haystack: &[u8]) -> Result<bool, FuzzyError> {
+        Ok(CompiledFuzzy::new(self.clone())?.is_match_bytes(haystack))
+    }
+
+    /// Checks whether this config and a hand-written regex agree on every sample
+    ///
+    /// Useful when migrating from manual regexes. Returns false if the config
+    /// fails to compile or the regex errors on a sample.
+    pub fn would_match_same(&self, other: &fancy_regex::Regex, samples: &[&str]) -> bool {
+        let Ok(matcher) = CompiledFuzzy::new(self.clone()) else {
+            return false;
+        };
+        samples.iter().all(|sample| match other.is_match(sample) {
+            Ok(expected) => {
+                let agrees = matcher.is_match(sample) == expected;
+                if !agrees {
+                    debug!("Config and regex disagree on: {}", sample);
+                }
+                agrees
+            }
+            Err(err) => {
+                error!("Regex error while matching: {}", err);
+                false
+            }
+        })
+    }
+
+    /// Finds the first match in the haystack, see [`CompiledFuzzy::find`]
+    pub fn find(&self, haystack: &str) -> Result<Option<FuzzyMatch>, FuzzyError> {
+        Ok(CompiledFuzzy::new(self.clone())?.find(haystack))
+    }
+
+    /// Finds every non-overlapping match, see [`CompiledFuzzy::find_all`]
+    pub fn find_all(&self, haystack: &str) -> Result<Vec<MatchRange>, FuzzyError> {
+        Ok(CompiledFuzzy::new(self.clone())?.find_all(haystack))
+    }
+
+    /// Checks whether any candidate matches, see [`CompiledFuzzy::any_match`]
+    pub fn any_match(&self, corpus: &[&str]) -> Result<bool, FuzzyError> {
+        Ok(CompiledFuzzy::new(self.clone())?.any_match(corpus.iter().copied()))
+    }
+
+    /// Scores a candidate, see [`CompiledFuzzy::score`]
+    pub fn score(&self, candidate: &str) -> Result<Option<f32>, FuzzyError> {
+        Ok(CompiledFuzzy::new(self.clone())?.score(candidate))
+    }
+
+    /// Returns the best score of any window of `window` characters in the haystack
+    ///
+    /// Scores relevance by the densest region of a long document rather than
+    /// by its first match. Haystacks shorter than the window are scored
+    /// whole. Returns `None` if no window matches, the window is empty, or the
+    /// config fails to compile.
+    pub fn window_score(&self, haystack: &str, window: usize) -> Option<f32> {
+        if window == 0 {
+            return None;
+        }
+        let matcher = CompiledFuzzy::new(self.clone()).ok()?;
+        let boundaries: Vec<usize> = haystack
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(std::iter::once(haystack.len()))
+            .collect();
+        let last_start = boundaries.len().saturating_sub(window + 1);
+        (0..=last_start)
+            .filter_map(|i| {
+                let end = boundaries[(i + window).min(boundaries.len() - 1)];
+                matcher.score(&haystack[boundaries[i]..end])
+            })
+            .max_by(f32::total_cmp)
+    }
+
+    /// Scores and sorts matching items, see [`CompiledFuzzy::search`]
+    pub fn search<'a, I: IntoIterator<Item = &'a str>>(
+        &self,
+        items: I,
+    ) -> Result<Vec<(&'a str, f32)>, FuzzyError> {
+        Ok(CompiledFuzzy::new(self.clone())?.search(items))
+    }
+
+    /// Scores and sorts matching items in parallel, see [`CompiledFuzzy::par_search`]
+    #[cfg(feature = "rayon")]
+    pub fn par_search<'a>(&self, items: &[&'a str]) -> Result<Vec<(&'a str, f32)>, FuzzyError> {
+        Ok(CompiledFuzzy::new(self.clone())?.par_search(items))
+    }
+
+    /// Ranks candidates by score, see [`CompiledFuzzy::rank`]
+    pub fn rank(&self, candidates: &[&str]) -> Result<Vec<(usize, f32)>, FuzzyError> {
+        Ok(CompiledFuzzy::new(self.clone())?.rank(candidates))
+    }
+
+    /// Ranks matching candidates with softmax weights that sum to 1
+    ///
+    /// Scores are divided by `temperature` before the softmax, so lower
+    /// temperatures favor the best candidates more strongly. Results keep the
+    /// order of [`rank`](Self::rank). A config that fails to compile, or no
+    /// matching candidate, gives an empty list.
+    pub fn rank_softmax<'a>(
+        &self,
+        candidates: &'a [&'a str],
+        temperature: f32,
+    ) -> Vec<(&'a str, f32)> {
+        let ranked = match self.rank(candidates) {
+            Ok(ranked) => ranked,
+            Err(err) => {
+                error!("Failed to rank candidates: {}", err);
+                return Vec::new();
+            }
+        };
+        let temperature = if temperature > 0.0 {
+            temperature
+        } else {
+            warn!(
+                "Non-positive softmax temperature {}, using {}",
+                temperature,
+                f32::EPSILON
+            );
+            f32::EPSILON
+        };
+        // Ranked best first, so the first score is the maximum used for stability
+        let Some(&(_, max)) = ranked.first() else {
+            return Vec::new();
+        };
+        let weights: Vec<f32> = ranked
+            .iter()
+            .map(|&(_, score)| ((score - max) / temperature).exp())
+            .collect();
+        let total: f32 = weights.iter().sum();
+        ranked
+            .iter()
+            .zip(weights)
+            .map(|(&(i, _), weight)| (candidates[i], weight / total))
+            .collect()
+    }
+
+    /// Orders candidates by match position, see [`CompiledFuzzy::rank_by_position`]
+    pub fn rank_by_position(&self, candidates: &[&str]) -> Result<Vec<(usize, usize)>, FuzzyError> {
+        Ok(CompiledFuzzy::new(self.clone())?.rank_by_position(candidates))
+    }
+
+    /// Finds every occurrence of each query word in the haystack
+    ///
+    /// Returns one entry per query word, in query order, with the byte ranges of
+    /// all its non-overlapping matches. Words are matched independently, so a
+    /// word is listed even if other words are missing.
+    pub fn matches_by_word(&self, haystack: &str) -> Result<WordMatches, FuzzyError> {
+        let config = self.normalized();
+        let regexes = config.word_regexes()?;
+        config
+            .query_tokens()
+            .into_iter()
+            .map(|token| token.text())
+            .zip(regexes)
+            .map(|(word, regex)| {
+                let mut spans = Vec::new();
+                for found in regex.find_iter(haystack) {
+                    let found = found?;
+                    if !found.range().is_empty() {
+                        spans.push(found.range());
+                    }
+                }
+                Ok((word.to_string(), spans))
+            })
+            .collect()
+    }
+
+    /// Scores each query word on its own against the haystack
+    ///
+    /// Returns one entry per query word, in query order, with the score of its
+    /// best occurrence found by [`matches_by_word`](Self::matches_by_word).
+    /// Missing words score 0.0, so partial matches still report every word.
+    pub fn word_scores(&self, haystack: &str) -> Result<Vec<(String, f32)>, FuzzyError> {
+        Ok(self
+            .matches_by_word(haystack)?
+            .into_iter()
+            .map(|(word, spans)| {
+                let score = spans
+                    .into_iter()
+                    .map(|span| self.span_score(&word, &haystack[span]))
+                    .fold(0.0, f32::max);
+                (word, score)
+            })
+            .collect())
+    }
+
+    /// Finds the haystack word that best matches a single-word query
+    ///
+    /// Each whitespace-delimited word of the haystack is scored on its own and
+    /// the best one is returned with its byte range; ties go to the earliest.
+    /// Multi-word queries return `None`.
+    pub fn best_word_match(&self, haystack: &str) -> Option<(Range<usize>, f32)> {
+        let matcher = CompiledFuzzy::new(self.clone()).ok()?;
+        if matcher.config().query_tokens().len() != 1 {
+            warn!("best_word_match needs a single-word query");
+            return None;
+        }
+        let mut best: Option<(Range<usize>, f32)> = None;
+        for word in haystack.split_whitespace() {
+            let Some(score) = matcher.score(word) else {
+                continue;
+            };
+            if best
+                .as_ref()
+                .is_none_or(|(_, best_score)| score > *best_score)
+            {
+                let start = word.as_ptr() as usize - haystack.as_ptr() as usize;
+                best = Some((start..start + word.len(), score));
+            }
+        }
+        best
+    }
+
+    /// Returns the shortest part of the first match that still matches the full query
+    ///
+    /// The first match is trimmed from the end, then from the start, as long as
+    /// the remaining text still matches the query on its own. Later, tighter
+    /// occurrences elsewhere in the haystack are not considered.
+    pub fn minimal_match<'a>(&self, haystack: &'a str) -> Option<&'a str> {
+        let matcher = CompiledFuzzy::new(self.clone()).ok()?;
+        let span = matcher.span(haystack)?;
+        let config = matcher.config();
+        let pattern =
+            create_fuzzy_pattern(&config.search_term, config, PatternShape::Exact).ok()?;
+        let exact = fancy_regex::Regex::new(&pattern).ok()?;
+        let matches = |range: Range<usize>| exact.is_match(&haystack[range]).unwrap_or(false);
+
+        let boundaries: Vec<usize> = haystack[span.clone()]
+            .char_indices()
+            .map(|(i, _)| span.start + i)
+            .chain(std::iter::once(span.end))
+            .collect();
+        let end = boundaries
+            .iter()
+            .copied()
+            .find(|&end| end > span.start && matches(span.start..end))
+            .unwrap_or(span.end);
+        let start = boundaries
+            .iter()
+            .rev()
+            .copied()
+            .filter(|&start| start < end)
+            .find(|&start| matches(start..end))
+            .unwrap_or(span.start);
+        debug!("Minimal match for {:?}: {}..{}", span, start, end);
+        Some(&haystack[start..end])
+    }
+
+    /// Returns the first match with up to `context` characters on each side
+    ///
+    /// Context is counted in graphemes and clamped to the haystack, and the
+    /// snippet never splits a grapheme. An ellipsis marks each truncated side.
+    pub fn snippet(&self, haystack: &str, context: usize) -> Option<String> {
+        let matcher = CompiledFuzzy::new(self.clone()).ok()?;
+        let span = matcher.span(haystack)?;
+        let window = context_window(haystack, &span, context)?;
+        Some(ellipsize(
+            haystack,
+            window.clone(),
+            haystack[window].to_string(),
+        ))
+    }
+
+    /// Searches the corpus, rendering each match for a terminal
+    ///
+    /// Each result shows the match with up to `context` graphemes on each
+    /// side, like [`snippet`](Self::snippet), with the matched spans wrapped in
+    /// [`ANSI_HIGHLIGHT`] and [`ANSI_RESET`]. Results are ordered like
+    /// [`rank`](Self::rank).
+    pub fn search_display<'a>(
+        &self,
+        corpus: &'a [&'a str],
+        context: usize,
+    ) -> Result<Vec<DisplayResult<'a>>, FuzzyError> {
+        let matcher = CompiledFuzzy::new(self.clone())?;
+        Ok(matcher
+            .rank(corpus)
+            .into_iter()
+            .filter_map(|(i, score)| {
+                let candidate = corpus[i];
+                let mut spans = matcher.group_spans(candidate)?;
+                spans.sort_by_key(|span| span.start);
+                let span = spans.first()?.start..spans.iter().map(|span| span.end).max()?;
+                let window = context_window(candidate, &span, context)?;
+
+                let mut highlighted = String::new();
+                let mut pos = window.start;
+                for span in merge_spans(&spans, 0) {
+                    highlighted.push_str(&candidate[pos..span.start]);
+                    highlighted.push_str(ANSI_HIGHLIGHT);
+                    highlighted.push_str(&candidate[span.clone()]);
+                    highlighted.push_str(ANSI_RESET);
+                    pos = span.end;
+                }
+                highlighted.push_str(&candidate[pos..window.end]);
+                Some(DisplayResult {
+                    candidate,
+                    display: ellipsize(candidate, window, highlighted),
+                    score,
+                })
+            })
+            .collect())
+    }
+
+    /// Wraps the parts of the haystack outside the match with dimming markers
+    ///
+    /// Matched spans are left bare. A haystack that doesn't match is wrapped
+    /// entirely.
+    pub fn highlight_inverse(
+        &self,
+        haystack: &str,
+        dim_open: &str,
+        dim_close: &str,
+    ) -> Result<String, FuzzyError> {
+        let mut highlighted = String::new();
+        for segment in self.highlight(haystack)? {
+            if segment.matched {
+                highlighted.push_str(&segment.text);
+            } else {
+                highlighted.push_str(dim_open);
+                highlighted.push_str(&segment.text);
+                highlighted.push_str(dim_close);
+            }
+        }
+        Ok(highlighted)
+    }
+
+    /// Splits the text into alternating matched and unmatched segments
+    ///
+    /// Matched segments cover the matched query words, so unordered terms can
+    /// give several. Segments are never empty, and joining their texts gives
+    /// back the input exactly. Text that doesn't match is one unmatched segment.
+    pub fn highlight(&self, text: &str) -> Result<Vec<Segment>, FuzzyError> {
+        let spans = CompiledFuzzy::new(self.clone())?
+            .group_spans(text)
+            .unwrap_or_default();
+
+        let mut segments = Vec::new();
+        let mut push = |range: Range<usize>, matched: bool| {
+            if !range.is_empty() {
+                segments.push(Segment {
+                    text: text[range].to_string(),
+                    matched,
+                });
+            }
+        };
+        let mut pos = 0;
+        for span in merge_spans(&spans, 0) {
+            push(pos..span.start, false);
+            push(span.clone(), true);
+            pos = span.end;
+        }
+        push(pos..text.len(), false);
+        Ok(segments)
+    }
+
+    /// Reports how each query character matched within the first match
+    ///
+    /// Query characters are aligned in order with the earliest matching
+    /// character of the match, and classified by the loosest rule needed:
+    /// exact, case folding, diacritic folding, then leetspeak equivalence when
+    /// `leet` is set. Optional characters missing from the match are left out,
+    /// and a haystack that doesn't match gives an empty list. Quotes and phone
+    /// formatting aren't query characters, and a bracket expression under
+    /// `char_classes` reports the character it matched as an exact match.
+    pub fn char_matches(&self, haystack: &str) -> Result<Vec<CharMatch>, FuzzyError> {
+        let matcher = CompiledFuzzy::new(self.clone())?;
+        let Some(span) = matcher.span(haystack) else {
+            return Ok(Vec::new());
+        };
+        Ok(align_units(matcher.config(), haystack, span)
+            .into_iter()
+            .map(|(_, found)| found)
+            .collect())
+    }
+
+    /// Returns the edits that turn the search term into the first matched span
+    ///
+    /// The edits come from a Levenshtein alignment of the term's characters,
+    /// as [`char_matches`](Self::char_matches) reads them, and the span,
+    /// comparing characters case-insensitively unless `case_sensitive` is set.
+    /// Returns `None` when the haystack doesn't match.
+    pub fn edit_script(&self, haystack: &str) -> Option<Vec<EditOp>> {
+        let matcher = CompiledFuzzy::new(self.clone()).ok()?;
+        let span = matcher.span(haystack)?;
+        let config = matcher.config();
+        let aligned = align_units(config, haystack, span.clone());
+        // Bracket expressions are spelled as the character they matched
+        let term: Vec<char> = config
+            .match_units(&config.search_term)
+            .iter()
+            .enumerate()
+            .flat_map(|(i, unit)| {
+                if is_class_unit(unit) {
+                    let matched = aligned.iter().find(|(unit, _)| *unit == i);
+                    vec![matched.map_or(unit_char(unit), |(_, found)| found.query)]
+                } else {
+                    unit.chars().collect()
+                }
+            })
+            .collect();
+        let matched: Vec<char> = haystack[span].chars().collect();
+        Some(levenshtein_script(
+            &term,
+            &matched,
+            config.is_case_sensitive(),
+        ))
+    }
+
+    /// Computes the Damerau-Levenshtein distance between the search term and a candidate
+    ///
+    /// Counts insertions, deletions, substitutions and swaps of adjacent
+    /// characters, over chars rather than bytes. Characters are compared
+    /// case-insensitively unless `case_sensitive` is set, and any normalizer
+    /// is applied to the term first. Unlike the generated pattern, the whole
+    /// candidate is compared, not a region of it.
+    pub fn edit_distance(&self, candidate: &str) -> usize {
+        let config = self.normalized();
+        let term: Vec<char> = config.search_term.chars().collect();
+        let candidate: Vec<char> = candidate.chars().collect();
+        damerau_levenshtein(&term, &candidate, config.is_case_sensitive())
+    }
+
+    /// Checks whether the candidate is within `max` edits of the search term
+    ///
+    /// See [`edit_distance`](Self::edit_distance).
+    pub fn within_distance(&self, candidate: &str, max: usize) -> bool {
+        self.edit_distance(candidate) <= max
+    }
+
+    /// Returns a stable hash of the generated pattern
+    ///
+    /// The hash is FNV-1a, which doesn't depend on the Rust version or the
+    /// platform, so it only changes when the generated pattern does. Configs
+    /// that fail to build a pattern hash the empty string.
+    pub fn pattern_fingerprint(&self) -> u64 {
+        const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0100_0000_01b3;
+        self.build_pattern()
+            .unwrap_or_default()
+            .bytes()
+            .fold(FNV_OFFSET, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+            })
+    }
+
+    /// Returns a rough node count of the generated pattern
+    ///
+    /// Larger counts mean slower compilation and matching. Configs that fail
+    /// to build a pattern report 0.
+    pub fn estimated_complexity(&self) -> usize {
+        match self.unchecked_pattern() {
+            Ok(pattern) => pattern_complexity(&pattern),
+            Err(_) => 0,
+        }
+    }
+
+    /// Compiles the pattern and returns the size of the compiled regex
+    ///
+    /// fancy_regex doesn't expose its program size, so this is the length in
+    /// bytes of the compiled pattern. Use it with
+    /// [`estimated_complexity`](Self::estimated_complexity) to preflight
+    /// expensive configs.
+    pub fn compiled_size(&self) -> Result<usize, FuzzyError> {
+        Ok(self.compile()?.as_str().len())
+    }
+
+    /// Estimates how prone the generated pattern is to catastrophic backtracking
+    ///
+    /// Nested variable quantifiers are reported as `High`; many overlapping
+    /// optional groups, unbounded repetitions or look-arounds as `Medium`.
+    /// Configs that fail to build a pattern report `Low` since no regex is run.
+    pub fn backtracking_risk(&self) -> RiskLevel {
+        let pattern = match self.build_pattern() {
+            Ok(pattern) => pattern,
+            Err(_) => return RiskLevel::Low,
+        };
+        let tree = match fancy_regex::Expr::parse_tree(&pattern) {
+            Ok(tree) => tree,
+            Err(err) => {
+                warn!("Could not analyze pattern {}: {}", pattern, err);
+                return RiskLevel::Low;
+            }
+        };
+
+        let mut counters = RiskCounters::default();
+        counters.visit(&tree.expr, false);
+        debug!("Backtracking analysis for {}: {:?}", pattern, counters);
+        counters.level()
+    }
+}
+
+/// A compiled fuzzy matcher that reuses its regex across many haystacks
+///
+/// Built with [`FuzzyConfig::compiled`] or [`CompiledFuzzy::new`]. Cloning
+/// recompiles the pattern from the stored config, so it costs as much as
+/// building a new matcher; share a reference instead where possible.
+#[derive(Debug)]
+pub struct CompiledFuzzy {
+    config: FuzzyConfig,
+    /// The compiled pattern, or `None` when matching falls back to edit distance
+    regex: Option<fancy_regex::Regex>,
+    /// Per-word regexes used to verify distinct word positions and weight words
+    word_regexes: Vec<fancy_regex::Regex>,
+    /// Shortest haystack length in bytes that could match, when prefiltering
+    min_len: usize,
+    /// Values of numeric query words, verified against the haystack under `numeric_tolerance`
+    numeric_targets: Vec<f64>,
+}
+
+/// Former name of [`CompiledFuzzy`]
+pub type FuzzyMatcher = CompiledFuzzy;
+
+impl Clone for CompiledFuzzy {
+    fn clone(&self) -> Self {
+        // The stored config already compiled once, so it compiles again
+        Self::new(self.config.clone()).expect("config compiled before")
+    }
+}
+
+impl CompiledFuzzy {
+    /// Compiles the configuration into a reusable matcher
+    ///
+    /// Any normalizer is applied to the search term here, so
+    /// [`config`](Self::config) returns the normalized term.
+    pub fn new(config: FuzzyConfig) -> Result<Self, FuzzyError> {
+        config.validate()?;
+        let config = config.normalized().into_owned();
+        let pattern = create_fuzzy_pattern(&config.search_term, &config, PatternShape::Captured)?;
+        let min_len = if config.length_prefilter {
+            config.min_match_len()
+        } else {
+            0
+        };
+        let numeric_targets: Vec<f64> = config
+            .query_tokens()
+            .into_iter()
+            .filter_map(|token| match token {
+                QueryToken::Word(word) => numeric_value(word, &config),
+                QueryToken::Phrase(_) => None,
+            })
+            .collect();
+        let oversized = config
+            .max_pattern_len
+            .is_some_and(|max| pattern.len() > max);
+        if oversized && config.streaming_fallback {
+            warn!(
+                "Pattern of {} bytes exceeds max_pattern_len, using edit distance fallback",
+                pattern.len()
+            );
+            return Ok(Self {
+                config,
+                regex: None,
+                word_regexes: Vec::new(),
+                min_len,
+                numeric_targets,
+            });
+        }
+        config.check_pattern_len(&pattern)?;
+        config.check_trivial(&pattern)?;
+        if config.max_pattern_complexity.is_some() {
+            // Check the pattern `build_pattern` gives, which the limit refers to
+            let search = create_fuzzy_pattern(&config.search_term, &config, PatternShape::Search)?;
+            config.check_complexity(&search)?;
+        }
+        let regex = Some(fancy_regex::Regex::new(&pattern)?);
+        let word_regexes = if config.unordered() && config.distinct_word_positions
+            || !config.idf_weights.is_empty()
+        {
+            config.word_regexes()?
+        } else {
+            Vec::new()
+        };
+        Ok(Self {
+            config,
+            regex,
+            word_regexes,
+            min_len,
+            numeric_targets,
+        })
+    }
+
+    /// Returns the configuration this matcher was built from
+    pub fn config(&self) -> &FuzzyConfig {
+        &self.config
+    }
+
+    /// Checks whether the haystack matches
+    pub fn is_match(&self, haystack: &str) -> bool {
+        self.span(haystack).is_some()
+    }
+
+    /// Checks whether a haystack that may not be valid UTF-8 matches
+    ///
+    /// fancy_regex only searches strings, so invalid sequences are replaced
+    /// with U+FFFD before matching. They never match a query character, though
+    /// gaps between characters may still cover them.
+    pub fn is_match_bytes(&self, haystack: &[u8]) -> bool {
+        self.is_match(&String::from_utf8_lossy(haystack))
+    }
+
+    /// Finds the first match in the haystack
+    ///
+    /// The match covers the matched query words, without the surrounding
+    /// context the pattern allows. Offsets always fall on char boundaries, and
+    /// empty matches are reported as `None`.
+    pub fn find(&self, haystack: &str) -> Option<FuzzyMatch> {
+        let span = self.span(haystack)?;
+        if span.is_empty() {
+            return None;
+        }
+        Some(FuzzyMatch {
+            start: span.start,
+            end: span.end,
+            text: haystack[span].to_string(),
+        })
+    }
+
+    /// Finds every non-overlapping match in the haystack
+    ///
+    /// Like the regex engine's own iteration, each search resumes where the
+    /// previous match ended, so a region overlapping an earlier match is never
+    /// reported; "aba" finds one match in "ababa". Unordered terms are
+    /// asserted over the whole haystack, so they give at most one match, and
+    /// in `grep_mode` each line gives at most one.
+    pub fn find_all(&self, haystack: &str) -> Vec<MatchRange> {
+        let mut matches = Vec::new();
+        let mut pos = 0;
+        while pos <= haystack.len() {
+            let Some(span) = self.span_from(haystack, pos) else {
+                break;
+            };
+            if span.start < pos {
+                break;
+            }
+            if span.is_empty() {
+                // Step over empty matches so the search always advances
+                pos = span.end
+                    + haystack[span.end..]
+                        .chars()
+                        .next()
+                        .map_or(1, char::len_utf8);
+                continue;
+            }
+            pos = span.end;
+            matches.push(MatchRange {
+                start: span.start,
+                end: span.end,
+            });
+        }
+        debug!("Found {} matches", matches.len());
+        matches
+    }
+
+    /// Checks whether any candidate matches
+    ///
+    /// Stops at the first match without scoring, so it is cheaper than
+    /// checking whether [`rank`](Self::rank) is empty.
+    pub fn any_match<I, S>(&self, candidates: I) -> bool
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        candidates
+            .into_iter()
+            .any(|candidate| self.is_match(candidate.as_ref()))
+    }
+
+    /// Scores the haystack, returning `None` when it doesn't match
+    ///
+    /// The score is `min(q, s) / max(q, s)`, where `q` is the number of
+    /// non-whitespace characters in the search term and `s` the number in the
+    /// matched span. An exact match scores 1.0, and every gap character or
+    /// missing optional character lowers the score, so matches using more of
+    /// `max_char_gap` score lower.
+    ///
+    /// With `idf_weights`, the score is instead the IDF-weighted average of this
+    /// formula applied to each word. With `order_bonus` or `soft_order`, it is
+    /// then multiplied by `1 - bonus * (1 - m)`, where `m` is the fraction of
+    /// adjacent words found in query order. The formula only changes with a
+    /// major version.
+    pub fn score(&self, haystack: &str) -> Option<f32> {
+        let spans = self.group_spans(haystack)?;
+        let span = spans_union(&spans)?;
+        let score = if self.config.idf_weights.is_empty() {
+            self.config
+                .span_score(&self.config.search_term, &haystack[span])
+        } else {
+            self.weighted_word_score(&haystack[span])
+        };
+        Some(score * (1.0 - self.config.effective_order_bonus() * (1.0 - monotonicity(&spans))))
+    }
+
+    /// Ranks candidates by score
+    ///
+    /// Returns `(index, score)` pairs for matching candidates, best score first.
+    /// Candidates with equal scores keep their original order. With
+    /// `dedup_results`, repeated candidates are only listed at their first index.
+    pub fn rank(&self, candidates: &[&str]) -> Vec<(usize, f32)> {
+        let mut seen = HashSet::new();
+        let mut scored: Vec<(usize, f32)> = candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, candidate)| !self.config.dedup_results || seen.insert(**candidate))
+            .filter_map(|(i, candidate)| self.score(candidate).map(|score| (i, score)))
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored
+    }
+
+    /// Scores and sorts matching items
+    ///
+    /// Returns the matching items with their scores, best score first. Ties
+    /// go to the shorter item, then to the earlier one.
+    pub fn search<'a, I: IntoIterator<Item = &'a str>>(&self, items: I) -> Vec<(&'a str, f32)> {
+        let mut scored: Vec<(&'a str, f32)> = items
+            .into_iter()
+            .filter_map(|item| self.score(item).map(|score| (item, score)))
+            .collect();
+        sort_search_results(&mut scored);
+        scored
+    }
+
+    /// Scores and sorts matching items in parallel
+    ///
+    /// Gives the same results as [`search`](Self::search), scoring items on
+    /// the rayon thread pool with this one compiled matcher. Worth it for
+    /// large corpora; small ones are faster sequentially.
+    #[cfg(feature = "rayon")]
+    pub fn par_search<'a>(&self, items: &[&'a str]) -> Vec<(&'a str, f32)> {
+        use rayon::prelude::*;
+        let mut scored: Vec<(&'a str, f32)> = items
+            .par_iter()
+            .filter_map(|&item| self.score(item).map(|score| (item, score)))
+            .collect();
+        sort_search_results(&mut scored);
+        scored
+    }
+
+    /// Yields the matching lines of a reader with their 1-based line numbers
+    ///
+    /// Lines are read one at a time, so the input is never loaded whole, and
+    /// returned without their line ending. Invalid UTF-8 is replaced with
+    /// U+FFFD as in [`is_match_bytes`](Self::is_match_bytes). Only the first
+    /// [`MAX_LINE_LEN`] bytes of a longer line are kept and matched. Read
+    /// errors are yielded as items; reading stops after one.
+    pub fn search_lines<'a, R: BufRead + 'a>(
+        &'a self,
+        mut reader: R,
+    ) -> impl Iterator<Item = io::Result<(usize, String)>> + 'a {
+        let mut line_number = 0;
+        let mut failed = false;
+        let mut buf = Vec::new();
+        std::iter::from_fn(move || {
+            while !failed {
+                buf.clear();
+                match read_bounded_line(&mut reader, &mut buf, MAX_LINE_LEN) {
+                    Ok(None) => return None,
+                    Ok(Some(truncated)) => {
+                        line_number += 1;
+                        if truncated {
+                            warn!(
+                                "Line {} exceeds {} bytes, matching its start only",
+                                line_number, MAX_LINE_LEN
+                            );
+                        }
+                        let line = String::from_utf8_lossy(&buf);
+                        if self.is_match(&line) {
+                            return Some(Ok((line_number, line.into_owned())));
+                        }
+                    }
+                    Err(e) => {
+                        failed = true;
+                        return Some(Err(e));
+                    }
+                }
+            }
+            None
+        })
+    }
+
+    /// Orders matching candidates by where their match starts
+    ///
+    /// Returns `(index, start)` pairs, where `start` is the byte offset of the
+    /// match, earliest first. Candidates with equal offsets keep their original order.
+    pub fn rank_by_position(&self, candidates: &[&str]) -> Vec<(usize, usize)> {
+        let mut positioned: Vec<(usize, usize)> = candidates
+            .iter()
+            .enumerate()
+            .filter_map(|(i, candidate)| self.span(candidate).map(|span| (i, span.start)))
+            .collect();
+        positioned.sort_by_key(|&(_, start)| start);
+        positioned
+    }
+
+    /// Checks whether the field extracted from a record matches
+    pub fn match_record<T, F: Fn(&T) -> String>(&self, record: &T, field: F) -> bool {
+        self.is_match(&field(record))
+    }
+
+    /// Ranks records by the score of an extracted field
+    ///
+    /// Returns the indices of matching records, best score first. Records with
+    /// equal scores keep their original order.
+    pub fn rank_records<T, F: Fn(&T) -> String>(&self, records: &[T], field: F) -> Vec<usize> {
+        let mut scored: Vec<(usize, f32)> = records
+            .iter()
+            .enumerate()
+            .filter_map(|(i, record)| self.score(&field(record)).map(|score| (i, score)))
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored.into_iter().map(|(i, _)| i).collect()
+    }
+
+    /// Finds the byte range of the first match in the haystack
+    ///
+    /// The range covers every capture group that participated in the match.
+    fn span(&self, haystack: &str) -> Option<Range<usize>> {
+        self.span_from(haystack, 0)
+    }
+
+    /// Like [`span`](Self::span), for the first match at or after `pos`
+    fn span_from(&self, haystack: &str, pos: usize) -> Option<Range<usize>> {
+        spans_union(&self.group_spans_from(haystack, pos)?)
+    }
+
+    /// Finds the byte ranges of the capture groups of the first match
+    ///
+    /// Ordered terms capture the whole match in one group, while unordered
+    /// terms capture each word separately, in query order.
+    fn group_spans(&self, haystack: &str) -> Option<Vec<Range<usize>>> {
+        self.group_spans_from(haystack, 0)
+    }
+
+    /// Like [`group_spans`](Self::group_spans), for the first match at or after `pos`
+    fn group_spans_from(&self, haystack: &str, pos: usize) -> Option<Vec<Range<usize>>> {
+        // Every character takes at least one byte, so this never drops a real match
+        if haystack.len() - pos < self.min_len {
+            return None;
+        }
+        let Some(regex) = &self.regex else {
+            let term: Vec<char> = self.config.search_term.chars().collect();
+            return edit_distance_span(
+                &term,
+                &haystack[pos..],
+                self.config.fallback_max_edits(),
+                self.config.is_case_sensitive(),
+            )
+            .map(|span| {
+                let shifted = span.start + pos..span.end + pos;
+                vec![shifted]
+            });
+        };
+        let mut spans = captured_spans(regex, haystack, pos)?;
+        if !self.numeric_targets.is_empty() {
+            spans = self.numeric_spans(regex, haystack, spans)?;
+        }
+        if self.config.tightest_span
+            && !self.config.unordered()
+            && self.config.search_term.split_whitespace().count() > 1
+        {
+            spans = tightest_spans(regex, haystack, spans);
+        }
+        if let Some(limit) = self.config.max_match_span {
+            if spans_width(&spans) > limit && !self.config.unordered() {
+                spans = tightest_spans(regex, haystack, spans);
+            }
+            if spans_width(&spans) > limit {
+                debug!("Match wider than {} bytes in: {}", limit, haystack);
+                return None;
+            }
+        }
+
+        if self.config.unordered()
+            && self.config.distinct_word_positions
+            && !self.has_distinct_word_positions(haystack)
+        {
+            debug!("Query words share haystack positions in: {}", haystack);
+            return None;
+        }
+        Some(spans)
+    }
+
+    /// Averages the scores of each query word within the match, weighted by `idf_weights`
+    ///
+    /// Each word scores like the whole term would on the first region its own
+    /// pattern matches, or 0.0 if it isn't found. Words without a weight count 1.0.
+    fn weighted_word_score(&self, matched: &str) -> f32 {
+        let (mut total, mut weights) = (0.0, 0.0);
+        for (token, regex) in self.config.query_tokens().iter().zip(&self.word_regexes) {
+            let word = token.text();
+            let weight = self
+                .config
+                .idf_weights
+                .get(&word.to_lowercase())
+                .copied()
+                .unwrap_or(1.0);
+            let score = match regex.find(matched) {
+                Ok(Some(found)) => self.config.span_score(word, found.as_str()),
+                Ok(None) => 0.0,
+                Err(err) => {
+                    error!("Regex error while matching: {}", err);
+                    0.0
+                }
+            };
+            total += weight * score;
+            weights += weight;
+        }
+        if weights > 0.0 {
+            total / weights
+        } else {
+            0.0
+        }
+    }
+
+    /// Finds the first match whose numbers are within tolerance of the query's
+    ///
+    /// The pattern accepts any number in place of a numeric query word, so
+    /// matches are checked in turn until one holds a close enough number for
+    /// every numeric query word.
+    fn numeric_spans(
+        &self,
+        regex: &fancy_regex::Regex,
+        haystack: &str,
+        mut spans: Vec<Range<usize>>,
+    ) -> Option<Vec<Range<usize>>> {
+        let tolerance = f64::from(self.config.numeric_tolerance.unwrap_or(0.0));
+        loop {
+            let start = spans.iter().map(|span| span.start).min()?;
+            let end = spans.iter().map(|span| span.end).max()?;
+            let numbers = parse_numbers(&haystack[start..end]);
+            let within = self.numeric_targets.iter().all(|target| {
+                numbers
+                    .iter()
+                    .any(|n| (n - target).abs() <= tolerance * target.abs())
+            });
+            if within {
+                return Some(spans);
+            }
+            // Unordered patterns are anchored at the start, so there is nothing to retry
+            if self.config.unordered() {
+                return None;
+            }
+            let next = start + haystack[start..].chars().next()?.len_utf8();
+            spans = captured_spans(regex, haystack, next)?;
+        }
+    }
+
+    /// Checks that every query word can be assigned its own non-overlapping match
+    fn has_distinct_word_positions(&self, haystack: &str) -> bool {
+        let optional = self.config.optional_words(&self.config.query_tokens());
+        let candidates: Vec<Vec<Range<usize>>> = self
+            .word_regexes
+            .iter()
+            .zip(optional)
+            .filter(|(_, optional)| !optional)
+            .map(|(regex, _)| {
+                regex
+                    .find_iter(haystack)
+                    .filter_map(Result::ok)
+                    .map(|m| m.range())
+                    .collect()
+            })
+            .collect();
+        assign_distinct_spans(&candidates, &mut Vec::new())
+    }
+}
+
+/// Sorts search results by score, then by length, keeping earlier items first on ties
+fn sort_search_results(scored: &mut [(&str, f32)]) {
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.len().cmp(&b.0.len())));
+}
+
+/// Picks one span per word so that no two chosen spans overlap
+fn assign_distinct_spans(candidates: &[Vec<Range<usize>>], chosen: &mut Vec<Range<usize>>) -> bool {
+    let Some((spans, rest)) = candidates.split_first() else {
+        return true;
+    };
+    for span in spans {
+        if chosen
+            .iter()
+            .any(|other| span.start < other.end && other.start < span.end)
+        {
+            continue;
+        }
+        chosen.push(span.clone());
+        if assign_distinct_spans(rest, chosen) {
+            return true;
+        }
+        chosen.pop();
+    }
+    false
+}
+
+/// Scores records with several text fields, each carrying its own weight
+#[derive(Debug, Clone)]
+pub struct WeightedSearch {
+    matcher: CompiledFuzzy,
+}
+
+impl WeightedSearch {
+    /// Compiles the configuration used for every field
+    pub fn new(config: FuzzyConfig) -> Result<Self, FuzzyError> {
+        Ok(Self {
+            matcher: CompiledFuzzy::new(config)?,
+        })
+    }
+
+    /// Scores a record given as `(text, weight)` pairs
+    ///
+    /// Returns the weighted sum of the scores of matching fields, or `None`
+    /// when no field matches.
+    pub fn score(&self, fields: &[(&str, f32)]) -> Option<f32> {
+        fields
+            .iter()
+            .filter_map(|&(text, weight)| self.matcher.score(text).map(|score| score * weight))
+            .reduce(|a, b| a + b)
+    }
+}
+
+/// Results from the tier of a [`FuzzyChain`] that produced them
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainResults {
+    /// Index of the tier that produced the results, strictest first
+    pub tier: usize,
+    /// `(index, score)` pairs for matching candidates, best score first
+    pub matches: Vec<(usize, f32)>,
+}
+
+/// An ordered list of progressively looser configurations
+///
+/// Searching tries the strictest tier first and only broadens to the next
+/// tier while fewer than `min_results` candidates match.
+#[derive(Debug, Clone)]
+pub struct FuzzyChain {
+    tiers: Vec<CompiledFuzzy>,
+    min_results: usize,
+}
+
+impl FuzzyChain {
+    /// Compiles the tiers, ordered from strictest to loosest
+    pub fn new(configs: Vec<FuzzyConfig>, min_results: usize) -> Result<Self, FuzzyError> {
+        if configs.is_empty() {
+            error!("Fuzzy chain needs at least one config");
+            return Err(FuzzyError::InvalidPattern(
+                "Fuzzy chain needs at least one config".to_string(),
+            ));
+        }
+        let tiers = configs
+            .into_iter()
+            .map(CompiledFuzzy::new)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { tiers, min_results })
+    }
+
+    /// Ranks the corpus with the strictest tier that finds enough results
+    ///
+    /// If no tier reaches `min_results`, the loosest tier's results are returned.
+    pub fn search(&self, corpus: &[&str]) -> ChainResults {
+        let last = self.tiers.len() - 1;
+        for (tier, matcher) in self.tiers.iter().enumerate() {
+            let matches = matcher.rank(corpus);
+            if matches.len() >= self.min_results || tier == last {
+                debug!("Fuzzy chain tier {} found {} results", tier, matches.len());
+                return ChainResults { tier, matches };
+            }
+            debug!(
+                "Fuzzy chain tier {} found only {} results, broadening",
+                tier,
+                matches.len()
+            );
+        }
+        unreachable!("chain has at least one tier")
+    }
+}
+
+/// Alternative spellings of a term, any of which may match
+///
+/// Each term gets the pattern the base config would generate for it, and the
+/// patterns are joined in a top-level alternation. The search term of the
+/// base config is ignored.
+#[derive(Debug, Clone)]
+pub struct MultiTermConfig {
+    config: FuzzyConfig,
+    terms: Vec<String>,
+}
+
+impl MultiTermConfig {
+    /// Creates a config matching any of the terms with the settings of `config`
+    pub fn new<I, S>(config: FuzzyConfig, terms: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            config,
+            terms: terms.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Creates the alternation of the terms' patterns
+    ///
+    /// The case flag is applied once at the front when every term uses the
+    /// same one. Otherwise, such as with `smart_caps`, each alternative keeps
+    /// its own flag, scoped to its group.
+    pub fn build_pattern(&self) -> Result<String, FuzzyError> {
+        if self.terms.is_empty() {
+            error!("No terms provided");
+            return Err(FuzzyError::EmptyPattern);
+        }
+        let alternatives = self
+            .terms
+            .iter()
+            .map(|term| {
+                let config = self.config.with_term(term.as_str());
+                config.validate()?;
+                let config = config.normalized();
+                let pattern =
+                    create_fuzzy_pattern(&config.search_term, &config, PatternShape::Search)?;
+                let case_flag = config.case_flag();
+                let body = pattern
+                    .strip_prefix(case_flag)
+                    .unwrap_or(&pattern)
+                    .to_string();
+                Ok((case_flag, body))
+            })
+            .collect::<Result<Vec<_>, FuzzyError>>()?;
+
+        let case_flag = alternatives[0].0;
+        let pattern = if alternatives.iter().all(|(flag, _)| *flag == case_flag) {
+            let bodies: Vec<&str> = alternatives.iter().map(|(_, body)| body.as_str()).collect();
+            format!("{}(?:{})", case_flag, bodies.join("|"))
+        } else {
+            let scoped: Vec<String> = alternatives
+                .iter()
+                .map(|(flag, body)| format!("(?:{}{})", flag, body))
+                .collect();
+            format!("(?:{})", scoped.join("|"))
+        };
+        debug!("Multi-term pattern: {}", pattern);
+        self.config.check_trivial(&pattern)?;
+        self.config.check_complexity(&pattern)?;
+        Ok(pattern)
+    }
+
+    /// Creates and compiles the alternation of the terms' patterns
+    pub fn compile(&self) -> Result<fancy_regex::Regex, FuzzyError> {
+        let pattern = self.build_pattern()?;
+        self.config.check_pattern_len(&pattern)?;
+        Ok(fancy_regex::Regex::new(&pattern)?)
+    }
+
+    /// Checks whether any of the terms matches the haystack
+    pub fn is_match(&self, haystack: &str) -> Result<bool, FuzzyError> {
+        Ok(self.compile()?.is_match(haystack)?)
+    }
+}
+
+/// A dictionary trie for finding words within an edit distance of a query
+///
+/// Searching walks the trie once, sharing the Levenshtein rows of common
+/// prefixes and pruning branches that can no longer stay within the
+/// distance, which is far cheaper than comparing the query to every word.
+#[derive(Debug, Clone)]
+pub struct TrieMatcher {
+    nodes: Vec<TrieNode>,
+    words: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct TrieNode {
+    children: BTreeMap<char, usize>,
+    /// Index into `words` if a word ends at this node
+    word: Option<usize>,
+}
+
+impl TrieMatcher {
+    /// Builds a trie from a dictionary of words
+    pub fn new<I, S>(words: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut trie = Self {
+            nodes: vec![TrieNode::default()],
+            words: Vec::new(),
+        };
+        for word in words {
+            trie.insert(word);
+        }
+        trie
+    }
+
+    /// Adds a word to the dictionary, ignoring duplicates
+    pub fn insert(&mut self, word: impl Into<String>) {
+        let word = word.into();
+        let mut node = 0;
+        for c in word.chars() {
+            node = match self.nodes[node].children.get(&c) {
+                Some(&child) => child,
+                None => {
+                    self.nodes.push(TrieNode::default());
+                    let child = self.nodes.len() - 1;
+                    self.nodes[node].children.insert(c, child);
+                    child
+                }
+            };
+        }
+        if self.nodes[node].word.is_none() {
+            self.nodes[node].word = Some(self.words.len());
+            self.words.push(word);
+        }
+    }
+
+    /// Returns the number of distinct words in the dictionary
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    /// Returns true if the dictionary has no words
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Finds all words within `max_dist` Levenshtein edits of the query
+    ///
+    /// Results are sorted by distance, then alphabetically.
+    pub fn search(&self, query: &str, max_dist: u32) -> Vec<(&str, u32)> {
+        let query: Vec<char> = query.chars().collect();
+        let first_row: Vec<usize> = (0..=query.len()).collect();
+        let mut results = Vec::new();
+        self.search_node(0, &query, &first_row, max_dist as usize, &mut results);
+        results.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(b.0)));
+        debug!("Trie search found {} words", results.len());
+        results
+    }
+
+    fn search_node<'a>(
+        &'a self,
+        node: usize,
+        query: &[char],
+        row: &[usize],
+        max_dist: usize,
+        results: &mut Vec<(&'a str, u32)>,
+    ) {
+        let distance = row[query.len()];
+        if let Some(word) = self.nodes[node].word {
+            if distance <= max_dist {
+                results.push((&self.words[word], distance as u32));
+            }
+        }
+        // No descendant can get closer than the best cell of this row
+        if row.iter().min().is_some_and(|&best| best > max_dist) {
+            return;
+        }
+        for (&c, &child) in &self.nodes[node].children {
+            let mut next = vec![row[0] + 1];
+            for (j, &q) in query.iter().enumerate() {
+                let cost = usize::from(q != c);
+                next.push((row[j + 1] + 1).min(next[j] + 1).min(row[j] + cost));
+            }
+            self.search_node(child, query, &next, max_dist, results);
+        }
+    }
+}
+
+/// Finds the haystack span closest to the term by edit distance
+///
+/// Aligns the whole term against any substring of the haystack and returns the
+/// earliest-ending span with the smallest distance, if within `max_edits`.
+fn edit_distance_span(
+    term: &[char],
+    haystack: &str,
+    max_edits: usize,
+    case_sensitive: bool,
+) -> Option<Range<usize>> {
+    // Each cell holds the distance of term[..i] ending here and where that alignment starts
+    let mut column: Vec<(usize, usize)> = (0..=term.len()).map(|i| (i, 0)).collect();
+    let mut best: Option<(usize, Range<usize>)> = None;
+    for (offset, c) in haystack.char_indices() {
+        let end = offset + c.len_utf8();
+        let mut next = vec![(0, end)];
+        for i in 1..=term.len() {
+            let cost = usize::from(!chars_equal(term[i - 1], c, case_sensitive));
+            let substitute = (column[i - 1].0 + cost, column[i - 1].1);
+            let insert = (column[i].0 + 1, column[i].1);
+            let delete = (next[i - 1].0 + 1, next[i - 1].1);
+            next.push(
+                [substitute, insert, delete]
+                    .into_iter()
+                    .min_by_key(|&(distance, _)| distance)
+                    .unwrap_or(substitute),
+            );
+        }
+        column = next;
+        let (distance, start) = column[term.len()];
+        if distance <= max_edits && best.as_ref().is_none_or(|(d, _)| distance < *d) {
+            best = Some((distance, start..end));
+        }
+    }
+    best.map(|(_, span)| span).filter(|span| !span.is_empty())
+}
+
+/// Compares two characters, folding case unless `case_sensitive` is set
+fn chars_equal(a: char, b: char, case_sensitive: bool) -> bool {
+    a == b || !case_sensitive && a.to_lowercase().eq(b.to_lowercase())
+}
+
+/// Computes the Levenshtein distance table between two character sequences
+fn levenshtein_table(a: &[char], b: &[char], case_sensitive: bool) -> Vec<Vec<usize>> {
+    let mut table = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in table.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in table[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(!chars_equal(a[i - 1], b[j - 1], case_sensitive));
+            table[i][j] = (table[i - 1][j] + 1)
+                .min(table[i][j - 1] + 1)
+                .min(table[i - 1][j - 1] + cost);
+        }
+    }
+    table
+}
+
+/// Walks the Levenshtein table back to recover the edits turning `a` into `b`
+fn levenshtein_script(a: &[char], b: &[char], case_sensitive: bool) -> Vec<EditOp> {
+    let table = levenshtein_table(a, b, case_sensitive);
+    let (mut i, mut j) = (a.len(), b.len());
+    let mut ops = Vec::new();
+    while i > 0 || j > 0 {
+        if i > 0 && j > 0 {
+            let same = chars_equal(a[i - 1], b[j - 1], case_sensitive);
+            let cost = usize::from(!same);
+            if table[i][j] == table[i - 1][j - 1] + cost {
+                if !same {
+                    ops.push(EditOp::Substitute {
+                        position: i - 1,
+                        from: a[i - 1],
+                        to: b[j - 1],
+                    });
+                }
+                i -= 1;
+                j -= 1;
+                continue;
+            }
+        }
+        if i > 0 && table[i][j] == table[i - 1][j] + 1 {
+            ops.push(EditOp::Delete {
+                position: i - 1,
+                ch: a[i - 1],
+            });
+            i -= 1;
+        } else {
+            ops.push(EditOp::Insert {
+                position: i,
+                ch: b[j - 1],
+            });
+            j -= 1;
+        }
+    }
+    ops.reverse();
+    ops
+}
+
+/// Computes the Damerau-Levenshtein distance between two character sequences
+///
+/// Uses the optimal string alignment variant, where a transposed pair can't
+/// be edited again.
+fn damerau_levenshtein(a: &[char], b: &[char], case_sensitive: bool) -> usize {
+    let mut table = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in table.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in table[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(!chars_equal(a[i - 1], b[j - 1], case_sensitive));
+            table[i][j] = (table[i - 1][j] + 1)
+                .min(table[i][j - 1] + 1)
+                .min(table[i - 1][j - 1] + cost);
+            if i > 1
+                && j > 1
+                && chars_equal(a[i - 1], b[j - 2], case_sensitive)
+                && chars_equal(a[i - 2], b[j - 1], case_sensitive)
+            {
+                table[i][j] = table[i][j].min(table[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    table[a.len()][b.len()]
+}
+
+/// Returns the ranges of the capture groups of the first match at or after `pos`
+fn captured_spans(
+    regex: &fancy_regex::Regex,
+    haystack: &str,
+    pos: usize,
+) -> Option<Vec<Range<usize>>> {
+    let captures = match regex.captures_from_pos(haystack, pos) {
+        Ok(captures) => captures?,
+        Err(err) => {
+            error!("Regex error while matching: {}", err);
+            return None;
+        }
+    };
+    let spans: Vec<Range<usize>> = captures
+        .iter()
+        .skip(1)
+        .flatten()
+        .map(|m| m.range())
+        .collect();
+    (!spans.is_empty()).then_some(spans)
+}
+
+/// Widens a span to the graphemes it touches plus `context` graphemes on each side
+fn context_window(haystack: &str, span: &Range<usize>, context: usize) -> Option<Range<usize>> {
+    let boundaries: Vec<usize> = haystack
+        .grapheme_indices(true)
+        .map(|(i, _)| i)
+        .chain(std::iter::once(haystack.len()))
+        .collect();
+    let first = boundaries.iter().rposition(|&b| b <= span.start)?;
+    let last = boundaries.iter().position(|&b| b >= span.end)?;
+    let first = first.saturating_sub(context);
+    let last = (last + context).min(boundaries.len() - 1);
+    Some(boundaries[first]..boundaries[last])
+}
+
+/// Adds an ellipsis on each side where the window truncates the haystack
+fn ellipsize(haystack: &str, window: Range<usize>, text: String) -> String {
+    let mut snippet = String::new();
+    if window.start > 0 {
+        snippet.push('…');
+    }
+    snippet.push_str(&text);
+    if window.end < haystack.len() {
+        snippet.push('…');
+    }
+    snippet
+}
+
+/// Returns the bytes covered from the first span start to the last span end
+fn spans_width(spans: &[Range<usize>]) -> usize {
+    let start = spans.iter().map(|span| span.start).min().unwrap_or(0);
+    let end = spans.iter().map(|span| span.end).max().unwrap_or(0);
+    end - start
+}
+
+/// Retries the match after each earlier start, keeping the shortest match found
+fn tightest_spans(
+    regex: &fancy_regex::Regex,
+    haystack: &str,
+    first: Vec<Range<usize>>,
+) -> Vec<Range<usize>> {
+    let mut best = first;
+    let mut start = best.iter().map(|span| span.start).min().unwrap_or(0);
+    while let Some(next) = haystack[start..]
+        .chars()
+        .next()
+        .map(|c| start + c.len_utf8())
+    {
+        let Some(spans) = captured_spans(regex, haystack, next) else {
+            break;
+        };
+        start = spans.iter().map(|span| span.start).min().unwrap_or(next);
+        if spans_width(&spans) < spans_width(&best) {
+            best = spans;
+        }
+    }
+    debug!("Tightest match spans: {:?}", best);
+    best
+}
+
+/// Returns the fraction of adjacent spans that start in ascending order
+///
+/// A single span counts as fully ordered.
+fn monotonicity(spans: &[Range<usize>]) -> f32 {
+    if spans.len() < 2 {
+        return 1.0;
+    }
+    let ordered = spans
+        .windows(2)
+        .filter(|w| w[0].start <= w[1].start)
+        .count();
+    ordered as f32 / (spans.len() - 1) as f32
+}
+
+/// How the matched region is embedded in a generated pattern
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PatternShape {
+    /// Matches anywhere in the haystack, as returned by `build_pattern`
+    Search,
+    /// Like `Search`, with the matched region in capture group 1
+    Captured,
+    /// Must match the entire haystack
+    Exact,
+}
+
+/// Creates a fuzzy search pattern with custom configuration
+///
+/// The `shape` decides how the matched region is embedded in the final pattern.
+fn create_fuzzy_pattern(
+    search_term: &str,
+    config: &FuzzyConfig,
+    shape: PatternShape,
+) -> Result<String, FuzzyError> {
+    if config.bag_of_chars {
+        if config.anchor != Anchor::Anywhere {
+            warn!("anchor is ignored by bag_of_chars");
+        }
+        let lookaheads = create_bag_pattern(search_term, config.is_case_sensitive())?;
+        let (case_flag, dotall) = (config.case_flag(), config.dotall_flag());
+        let start = config.line_start();
+        return Ok(match shape {
+            PatternShape::Search => format!("{}{}{}{}", case_flag, dotall, start, lookaheads),
+            // Characters may come from anywhere, so the whole haystack (or line) is the match
+            PatternShape::Captured => {
+                format!("{}{}{}{}(.*)", case_flag, dotall, start, lookaheads)
+            }
+            PatternShape::Exact => format!("{}{}^{}.*$", case_flag, dotall, lookaheads),
+        });
+    }
+
+    if config.gap_allows_whitespace.is_none() && config.max_char_gap > LEGACY_WHITESPACE_GAP {
+        warn!(
+            "Gaps above {} allowing whitespace is deprecated, set gap_allows_whitespace",
+            LEGACY_WHITESPACE_GAP
+        );
+    }
+    let words = create_word_patterns(search_term, config)?;
+    let optional = config.optional_words(&parse_query(search_term, config.quoted_phrases));
+
+    let case_flag = config.case_flag();
+    // Without word order, each word is asserted by its own lookahead from the start
+    if config.unordered() && words.len() > 1 {
+        if config.sentence_start {
+            warn!("sentence_start is ignored when word order is ignored");
+        }
+        let mut lookaheads: String = words
+            .iter()
+            .zip(&optional)
+            .map(|(word, &optional)| match shape {
+                // Optional words are only captured for scoring
+                PatternShape::Captured if optional => format!("(?=(?:.*?({}))?)", word),
+                _ if optional => String::new(),
+                PatternShape::Captured => format!("(?=.*?({}))", word),
+                _ => format!("(?=.*?{})", word),
+            })
+            .collect();
+        // Some word has to sit at each anchored end
+        let any_word = words.join("|");
+        if config.anchor.at_start() {
+            lookaheads.push_str(&format!("(?=(?:{}))", any_word));
+        }
+        if config.anchor.at_end() {
+            lookaheads.push_str(&format!("(?=.*?(?:{})$)", any_word));
+        }
+        if config.grep_mode {
+            return Ok(format!("{}(?m)^{}", case_flag, lookaheads));
+        }
+        let start = if config.anchor.at_start() {
+            "^"
+        } else {
+            config.line_start()
+        };
+        return Ok(format!(
+            "{}{}{}{}",
+            case_flag,
+            config.dotall_flag(),
+            start,
+            lookaheads
+        ));
+    }
+
+    // For multiple words, require all words to be present with flexible whitespace
+    let body = if words.len() > 1 {
+        let space = config.space_class();
+        let separator = match config.max_words_between {
+            // Bound the number of whole words allowed between query words
+            Some(max) => format!(
+                "{space}+(?:[^\\s\\p{{Z}}\\p{{C}}]+{space}+){{0,{max}}}[^\\s\\p{{Z}}\\p{{C}}]*?"
+            ),
+            None => format!("{}+.*?", space),
+        };
+        // Optional words carry the separator toward the nearest required word
+        let mut body = String::new();
+        let mut seen_required = false;
+        for (word, &optional) in words.iter().zip(&optional) {
+            body.push_str(&match (seen_required, optional) {
+                (false, true) => format!("(?:{}{})?", word, separator),
+                (false, false) => word.clone(),
+                (true, true) => format!("(?:{}{})?", separator, word),
+                (true, false) => format!("{}{}", separator, word),
+            });
+            seen_required |= !optional;
+        }
+        body
+    } else {
+        words[0].clone()
+    };
+    // Only start the match at the start of the text or after sentence-ending punctuation
+    let start = if config.sentence_start {
+        SENTENCE_START
+    } else {
+        ""
+    };
+    let anchor = config.anchor;
+    if config.grep_mode {
+        // Lines are matched one at a time, so no trailing context is needed
+        let lead = if anchor.at_start() { "^" } else { "^.*?" };
+        let end = if anchor.at_end() { "$" } else { "" };
+        return match shape {
+            PatternShape::Search => {
+                Ok(format!("{}(?m){}{}{}{}", case_flag, lead, start, body, end))
+            }
+            PatternShape::Captured => Ok(format!(
+                "{}(?m){}{}({}){}",
+                case_flag, lead, start, body, end
+            )),
+            PatternShape::Exact => Ok(format!("{}^(?:{})$", case_flag, body)),
+        };
+    }
+    let lead = if anchor.at_start() { "^" } else { ".*?" };
+    let end = if anchor.at_end() { "$" } else { ".*?" };
+    let dotall = config.dotall_flag();
+    match shape {
+        PatternShape::Search => Ok(format!(
+            "{}{}{}{}{}{}",
+            case_flag, dotall, lead, start, body, end
+        )),
+        // Capture the matched region so callers can recover its span
+        PatternShape::Captured => Ok(format!(
+            "{}{}{}{}({}){}",
+            case_flag, dotall, lead, start, body, end
+        )),
+        PatternShape::Exact => Ok(format!("{}{}^(?:{})$", case_flag, dotall, body)),
+    }
+}
+
+/// Assertion that a match starts a sentence, followed by any leading whitespace
+const SENTENCE_START: &str = "(?:^|(?<=[.!?]))[\\s\\p{Z}]*";
+
+/// Splits the search term into words and creates a pattern for each
+fn create_word_patterns(
+    search_term: &str,
+    config: &FuzzyConfig,
+) -> Result<Vec<String>, FuzzyError> {
+    // Validate search term
+    if search_term.trim().is_empty() {
+        error!("Empty search term provided");
+        return Err(FuzzyError::EmptyPattern);
+    }
+
+    // Split search term into words and quoted phrases
+    let words = parse_query(search_term, config.quoted_phrases);
+
+    if words.is_empty() {
+        error!("No valid words found in search term");
+        return Err(FuzzyError::EmptyPattern);
+    }
+
+    // Check minimum word length requirement
+    if words
+        .iter()
+        .any(|w| w.text().chars().count() < config.min_word_length)
+    {
+        debug!(
+            "Words shorter than minimum length {} are matched exactly: {:?}",
+            config.min_word_length, words
+        );
+    }
+
+    // Per-word case settings only apply when there is one for every word
+    let per_word_case = config.per_word_case(words.len());
+
+    // Split on whitespace but preserve punctuation
+    let words: Vec<String> = words
+        .into_iter()
+        .enumerate()
+        .map(|(i, token)| {
+            let case_sensitive = per_word_case.map_or(config.is_case_sensitive(), |cases| cases[i]);
+            let word = match token {
+                QueryToken::Word(word) => config.truncate_word(word),
+                QueryToken::Phrase(phrase) => {
+                    return Ok(create_phrase_pattern(phrase, case_sensitive));
+                }
+            };
+            let pattern = if numeric_value(word, config).is_some() {
+                NUMBER_PATTERN.to_string()
+            } else if let Some(digits) = phone_digits(word, config) {
+                create_phone_pattern(&digits)
+            } else if config.anagram {
+                create_anagram_pattern(word, case_sensitive)?
+            } else {
+                // Words with punctuation are split so they match with or without the punctuation
+                let parts = word_parts(word, config);
+                for part in &parts {
+                    validate_char_classes(part, config)?;
+                }
+                let parts: Vec<String> = parts
+                    .into_iter()
+                    .map(|part| create_word_pattern(part, config, case_sensitive))
+                    .collect();
+                // Parts may be separated by whitespace or by the punctuation that split them
+                parts.join(&format!("(?:{}|[\\p{{P}}\\p{{S}}])*", config.space_class()))
+            };
+            // Known abbreviations and expansions also match the other form literally
+            let pattern = match config.abbreviation_for(word) {
+                Some(other) => {
+                    let other = fancy_regex::escape(other);
+                    let other = if case_sensitive {
+                        format!("(?-i:{})", other)
+                    } else {
+                        format!("(?i:{})", other)
+                    };
+                    debug!("Abbreviation alternative for '{}': {}", word, other);
+                    format!("(?:{}|{})", pattern, other)
+                }
+                None => pattern,
+            };
+            let pattern = match config.word_anchoring {
+                WordAnchor::Free => pattern,
+                WordAnchor::Prefix => format!("(?<!\\S){}", pattern),
+                WordAnchor::Suffix => format!("{}(?!\\S)", pattern),
+            };
+            Ok(match per_word_case {
+                // Scope the case flag to the word since there is no global flag
+                Some(_) if case_sensitive => format!("(?-i:{})", pattern),
+                Some(_) => format!("(?i:{})", pattern),
+                None => pattern,
+            })
+        })
+        .collect::<Result<_, FuzzyError>>()?;
+
+    Ok(words)
+}
+
+/// Longest word accepted in anagram mode
+const MAX_ANAGRAM_LEN: usize = 32;
+
+/// Creates a pattern matching any rearrangement of a word's characters
+///
+/// The pattern matches a whitespace-delimited haystack word of the same length,
+/// made only of the query's characters, with one lookahead per distinct
+/// character asserting it occurs often enough. Each lookahead scans the word,
+/// so matching costs O(distinct characters × length) per position, and words
+/// are capped at [`MAX_ANAGRAM_LEN`] characters.
+fn create_anagram_pattern(word: &str, case_sensitive: bool) -> Result<String, FuzzyError> {
+    let len = word.chars().count();
+    if len > MAX_ANAGRAM_LEN {
+        error!("Anagram word too long: {}", word);
+        return Err(FuzzyError::InvalidPattern(format!(
+            "anagram words are limited to {} characters, got {}",
+            MAX_ANAGRAM_LEN, len
+        )));
+    }
+
+    let mut counts: BTreeMap<char, usize> = BTreeMap::new();
+    for c in word.chars() {
+        let c = if case_sensitive {
+            c
+        } else {
+            c.to_lowercase().next().unwrap_or(c)
+        };
+        *counts.entry(c).or_default() += 1;
+    }
+    let variants = |c: char| -> String {
+        let mut variants = class_escape(c);
+        if !case_sensitive {
+            for upper in c.to_uppercase().filter(|&u| u != c) {
+                variants.push_str(&class_escape(upper));
+            }
+        }
+        variants
+    };
+    let set: String = counts.keys().map(|&c| variants(c)).collect();
+
+    let mut pattern = format!("(?<!\\S)(?=[{}]{{{}}}(?!\\S))", set, len);
+    for (&c, &count) in &counts {
+        let variants = variants(c);
+        pattern.push_str(&format!(
+            "(?=(?:[^\\s{}]*[{}]){{{}}})",
+            variants, variants, count
+        ));
+    }
+    pattern.push_str(&format!("[{}]{{{}}}", set, len));
+    debug!("Anagram pattern for {}: {}", word, pattern);
+    Ok(pattern)
+}
+
+/// Creates lookaheads requiring each character of the term as often as it occurs
+///
+/// Whitespace in the term is ignored, and so are order and adjacency.
+fn create_bag_pattern(search_term: &str, case_sensitive: bool) -> Result<String, FuzzyError> {
+    let mut counts: BTreeMap<char, usize> = BTreeMap::new();
+    for c in search_term.chars().filter(|c| !c.is_whitespace()) {
+        let c = if case_sensitive {
+            c
+        } else {
+            c.to_lowercase().next().unwrap_or(c)
+        };
+        *counts.entry(c).or_default() += 1;
+    }
+    if counts.is_empty() {
+        error!("Empty search term provided");
+        return Err(FuzzyError::EmptyPattern);
+    }
+
+    let mut pattern = String::new();
+    for (&c, &count) in &counts {
+        let mut variants = class_escape(c);
+        if !case_sensitive {
+            for upper in c.to_uppercase().filter(|&u| u != c) {
+                variants.push_str(&class_escape(upper));
+            }
+        }
+        pattern.push_str(&format!("(?=(?:.*?[{}]){{{}}})", variants, count));
+    }
+    debug!("Bag of characters pattern for {}: {}", search_term, pattern);
+    Ok(pattern)
+}
+
+/// Escapes a character for use inside a bracketed character class
+fn class_escape(c: char) -> String {
+    if "\\[]^-&~".contains(c) {
+        format!("\\{}", c)
+    } else {
+        c.to_string()
+    }
+}
+
+/// Splits a word on punctuation into the parts that get their own pattern
+///
+/// Words containing bracket expressions under `char_classes` are kept whole.
+fn word_parts<'a>(word: &'a str, config: &FuzzyConfig) -> Vec<&'a str> {
+    let has_class = split_units(word, config)
+        .iter()
+        .any(|unit| is_class_unit(unit));
+    if has_class {
+        return vec![word];
+    }
+    let split_punctuation =
+        !config.preserve_punctuation_position && word.chars().any(|c| c.is_ascii_punctuation());
+    let parts: Vec<&str> = if split_punctuation {
+        word.split(|c: char| c.is_ascii_punctuation())
+            .filter(|s| !s.is_empty())
+            .collect()
+    } else {
+        vec![word]
+    };
+    if !config.camel_case {
+        return parts;
+    }
+    parts.into_iter().flat_map(split_camel_case).collect()
+}
+
+/// Splits a camelCase word, keeping runs of capitals together as acronyms
+///
+/// "JSONParser" splits into "JSON" and "Parser", and "parseJSON" into
+/// "parse" and "JSON".
+fn split_camel_case(word: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = word.char_indices().collect();
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for i in 1..chars.len() {
+        let (pos, c) = chars[i];
+        let prev = chars[i - 1].1;
+        let next_lower = chars.get(i + 1).is_some_and(|&(_, n)| n.is_lowercase());
+        // A capital starts a word after a lowercase letter or digit, and also
+        // ends an acronym when a lowercase letter follows it
+        let boundary = c.is_uppercase()
+            && (prev.is_lowercase() || prev.is_numeric() || prev.is_uppercase() && next_lower);
+        if boundary {
+            parts.push(&word[start..pos]);
+            start = pos;
+        }
+    }
+    parts.push(&word[start..]);
+    parts
+}
+
+/// Splits a word into single characters, keeping `[...]` bracket expressions whole
+///
+/// Bracket expressions are only recognized when `char_classes` is set; an
+/// unclosed or empty bracket is treated as a plain character.
+fn split_units<'a>(word: &'a str, config: &FuzzyConfig) -> Vec<&'a str> {
+    let mut units = Vec::new();
+    let mut rest = word;
+    while let Some(c) = rest.chars().next() {
+        let unit_len = match rest.find(']') {
+            Some(close) if config.char_classes && c == '[' && close > 1 => close + 1,
+            _ if config.emoji_aware && is_emoji(c) => {
+                rest.graphemes(true).next().map_or(c.len_utf8(), str::len)
+            }
+            _ => c.len_utf8(),
+        };
+        units.push(&rest[..unit_len]);
+        rest = &rest[unit_len..];
+    }
+    units
+}
+
+/// Returns true if the unit is a bracket expression rather than a single character
+fn is_class_unit(unit: &str) -> bool {
+    unit.chars().count() > 2 && unit.starts_with('[') && unit.ends_with(']')
+}
+
+/// Returns the character standing in for a unit when choosing gaps
+fn unit_char(unit: &str) -> char {
+    let mut chars = unit.chars();
+    let first = chars.next().unwrap_or_default();
+    if is_class_unit(unit) {
+        chars.find(|&c| c != '^').unwrap_or(first)
+    } else {
+        first
+    }
+}
+
+/// Checks that every bracket expression in the word is a valid character class
+fn validate_char_classes(word: &str, config: &FuzzyConfig) -> Result<(), FuzzyError> {
+    for unit in split_units(word, config) {
+        if is_class_unit(unit) && fancy_regex::Regex::new(unit).is_err() {
+            error!("Invalid character class in search term: {}", unit);
+            return Err(FuzzyError::InvalidPattern(format!(
+                "invalid character class {}",
+                unit
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Checks whether the text has letters and all of them are uppercase
+fn is_all_caps(text: &str) -> bool {
+    let mut letters = text.chars().filter(|c| c.is_alphabetic()).peekable();
+    letters.peek().is_some() && letters.all(char::is_uppercase)
+}
+
+/// How the required character count is rounded from `required_char_ratio`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RatioRounding {
+    /// Round up, requiring at least the ratio of characters
+    #[default]
+    Ceil,
+    /// Round down, requiring at most the ratio of characters
+    Floor,
+    /// Round to the nearest count
+    Round,
+}
+
+/// Where each query word must sit within a haystack word
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum WordAnchor {
+    /// Anywhere, as a substring of a haystack word
+    #[default]
+    Free,
+    /// At the start of a whitespace-delimited haystack word
+    Prefix,
+    /// At the end of a whitespace-delimited haystack word
+    Suffix,
+}
+
+/// Where the match must sit within the haystack
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Anchor {
+    /// Anywhere in the haystack
+    #[default]
+    Anywhere,
+    /// At the start of the haystack
+    Start,
+    /// At the end of the haystack
+    End,
+    /// Covering the whole haystack
+    Exact,
+}
+
+impl Anchor {
+    fn at_start(self) -> bool {
+        matches!(self, Anchor::Start | Anchor::Exact)
+    }
+
+    fn at_end(self) -> bool {
+        matches!(self, Anchor::End | Anchor::Exact)
+    }
+}
+
+/// A Unicode normalization form
+#[cfg(feature = "unicode-normalization")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Normalization {
+    /// Canonical composition, turning "e" and a combining accent into "é"
+    Nfc,
+    /// Compatibility composition, which also folds forms such as "ﬁ" into "fi"
+    Nfkc,
+    /// Canonical decomposition, turning "é" into "e" and a combining accent,
+    /// as in macOS file names
+    Nfd,
+    /// Compatibility decomposition, which also folds forms such as "ﬁ" into "fi"
+    Nfkd,
+}
+
+#[cfg(feature = "unicode-normalization")]
+impl Normalization {
+    /// Normalizes the text to this form
+    pub fn apply(self, text: &str) -> String {
+        use unicode_normalization::UnicodeNormalization;
+        match self {
+            Normalization::Nfc => text.nfc().collect(),
+            Normalization::Nfkc => text.nfkc().collect(),
+            Normalization::Nfd => text.nfd().collect(),
+            Normalization::Nfkd => text.nfkd().collect(),
+        }
+    }
+}
+
+/// How characters are compared when matching case-insensitively
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CaseFold {
+    /// One-to-one mappings, so "ß" only matches itself and "ẞ"
+    #[default]
+    Simple,
+    /// Also expands characters folding to several, so "ß" matches "ss" and "ﬁ" matches "fi"
+    ///
+    /// The Turkish dotted and dotless I are folded without a locale: "i" also
+    /// matches "İ", "I" also matches "ı", and "İ" and "ı" match "i" and "I".
+    Full,
+}
+
+/// Pre-tuned settings for [`FuzzyConfig::preset`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    /// Exact spelling: `required_char_ratio` 1.0, `max_char_gap` 0
+    Strict,
+    /// Small typos: `required_char_ratio` 0.8, `max_char_gap` 1
+    Typo,
+    /// Broad matches: `required_char_ratio` 0.4, `max_char_gap` 10
+    Loose,
+    /// Prefix completion: `anchor` Start, `required_char_ratio` 0.6
+    Autocomplete,
+}
+
+/// A keyboard layout whose adjacent keys `keyboard_layout` tolerates as typos
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum KeyboardLayout {
+    /// The US QWERTY layout
+    Qwerty,
+}
+
+impl KeyboardLayout {
+    /// Returns the lowercase letters on keys adjacent to the letter's key
+    ///
+    /// Characters without a known key have no neighbors.
+    pub fn neighbors(self, c: char) -> &'static str {
+        let table = match self {
+            KeyboardLayout::Qwerty => QWERTY_ADJACENCY,
+        };
+        let lower = c.to_ascii_lowercase();
+        table
+            .iter()
+            .find(|(key, _)| *key == lower)
+            .map_or("", |(_, neighbors)| neighbors)
+    }
+}
+
+/// A piece of the search term
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum QueryToken<'a> {
+    /// A word matched fuzzily
+    Word(&'a str),
+    /// A quoted phrase matched exactly
+    Phrase(&'a str),
+}
+
+impl<'a> QueryToken<'a> {
+    /// Returns the text of the token, without quotes
+    fn text(&self) -> &'a str {
+        match self {
+            QueryToken::Word(text) | QueryToken::Phrase(text) => text,
+        }
+    }
+}
+
+/// Splits the search term into words, keeping quoted sections together as phrases
+///
+/// Quotes pair up from left to right, so a quote inside a phrase closes it.
+/// An unclosed quote is ignored and the text after it is split into words.
+fn parse_query(search_term: &str, quoted_phrases: bool) -> Vec<QueryToken<'_>> {
+    if !quoted_phrases {
+        return search_term
+            .split_whitespace()
+            .map(QueryToken::Word)
+            .collect();
+    }
+    let segments: Vec<&str> = search_term.split('"').collect();
+    let last = segments.len() - 1;
+    let mut tokens = Vec::new();
+    for (i, segment) in segments.into_iter().enumerate() {
+        let quoted = i % 2 == 1 && i != last;
+        if i % 2 == 1 && i == last {
+            warn!("Unbalanced quote in search term: {}", search_term);
+        }
+        if quoted {
+            let phrase = segment.trim();
+            if !phrase.is_empty() {
+                tokens.push(QueryToken::Phrase(phrase));
+            }
+        } else {
+            tokens.extend(segment.split_whitespace().map(QueryToken::Word));
+        }
+    }
+    debug!("Parsed query tokens: {:?}", tokens);
+    tokens
+}
+
+/// Creates a pattern matching a phrase exactly, allowing any whitespace between its words
+fn create_phrase_pattern(phrase: &str, case_sensitive: bool) -> String {
+    let pattern = phrase
+        .split_whitespace()
+        .map(|word| fancy_regex::escape(word).into_owned())
+        .collect::<Vec<_>>()
+        .join("[\\s\\p{Z}]+");
+    debug!("Exact phrase pattern: {}", pattern);
+    if case_sensitive {
+        format!("(?-i:{})", pattern)
+    } else {
+        format!("(?i:{})", pattern)
+    }
+}
+
+/// Currency symbols allowed before a number under `numeric_tolerance`
+const CURRENCY_SYMBOLS: &[char] = &['$', '€', '£', '¥'];
+
+/// Pattern accepting any number, with an optional currency symbol and thousands separators
+const NUMBER_PATTERN: &str = "(?:[$€£¥]?\\d[\\d,]*(?:\\.\\d+)?)";
+
+/// Returns the value of a numeric token when numeric tolerance is enabled
+fn numeric_value(word: &str, config: &FuzzyConfig) -> Option<f64> {
+    config.numeric_tolerance?;
+    let digits = word.trim_start_matches(CURRENCY_SYMBOLS);
+    if !digits.starts_with(|c: char| c.is_ascii_digit())
+        || !digits
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == ',' || c == '.')
+    {
+        return None;
+    }
+    digits.replace(',', "").parse().ok()
+}
+
+/// Parses every number in the text, ignoring thousands separators
+fn parse_numbers(text: &str) -> Vec<f64> {
+    let mut numbers = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(|c: char| c.is_ascii_digit()) {
+        let len = rest[start..]
+            .find(|c: char| !(c.is_ascii_digit() || c == ',' || c == '.'))
+            .unwrap_or(rest.len() - start);
+        let number = rest[start..start + len].trim_end_matches(['.', ',']);
+        if let Ok(value) = number.replace(',', "").parse() {
+            numbers.push(value);
+        }
+        rest = &rest[start + len..];
+    }
+    numbers
+}
+
+/// Characters that format phone numbers without being part of the number
+const PHONE_FORMATTING: &[char] = &[' ', '-', '(', ')', '.', '+'];
+
+/// Checks whether the unit is a single phone formatting character
+fn is_phone_formatting(unit: &str) -> bool {
+    let mut chars = unit.chars();
+    chars.next().is_some_and(|c| PHONE_FORMATTING.contains(&c)) && chars.next().is_none()
+}
+
+/// Returns the digits of a numeric token when phone mode is enabled
+fn phone_digits(word: &str, config: &FuzzyConfig) -> Option<String> {
+    if !config.phone_mode
+        || !word.chars().any(|c| c.is_ascii_digit())
+        || !word
+            .chars()
+            .all(|c| c.is_ascii_digit() || PHONE_FORMATTING.contains(&c))
+    {
+        return None;
+    }
+    Some(word.chars().filter(char::is_ascii_digit).collect())
+}
+
+/// Creates a pattern matching a digit sequence with any formatting between digits
+fn create_phone_pattern(digits: &str) -> String {
+    let pattern = digits
+        .chars()
+        .map(String::from)
+        .collect::<Vec<_>>()
+        .join("[\\s\\-().+]*");
+    debug!("Phone number pattern: {}", pattern);
+    format!("(?:{})", pattern)
+}
+
+/// Checks whether the character is an emoji base or flag letter
+fn is_emoji(c: char) -> bool {
+    matches!(c as u32, 0x1F000..=0x1FAFF | 0x2600..=0x27BF) && !is_emoji_modifier(c)
+}
+
+/// Checks whether the character is a skin tone modifier or emoji presentation selector
+fn is_emoji_modifier(c: char) -> bool {
+    matches!(c as u32, 0x1F3FB..=0x1F3FF | 0xFE0F)
+}
+
+/// Creates a pattern matching an emoji sequence with or without skin tones
+///
+/// Each part of a ZWJ sequence matches its base emoji, optionally followed by
+/// a skin tone modifier or presentation selector, whatever the query used.
+fn create_emoji_pattern(emoji: &str) -> String {
+    emoji
+        .split('\u{200D}')
+        .map(|part| {
+            let base: String = part.chars().filter(|&c| !is_emoji_modifier(c)).collect();
+            format!(
+                "{}[\\x{{1F3FB}}-\\x{{1F3FF}}\\x{{FE0F}}]*",
+                fancy_regex::escape(&base)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\\x{200D}")
+}
+
+/// Creates a pattern matching exactly one character, case-folded unless case-sensitive
+///
+/// Cases of one character each give a class such as `[aA]`; mappings to
+/// several characters, such as "ß" to "SS", give an alternation instead.
+fn literal_char_pattern(c: char, case_sensitive: bool) -> String {
+    let lower: String = c.to_lowercase().collect();
+    let upper: String = c.to_uppercase().collect();
+    if case_sensitive || lower == upper {
+        return fancy_regex::escape(&c.to_string()).into_owned();
+    }
+    if lower.chars().count() == 1 && upper.chars().count() == 1 {
+        return format!("[{}{}]", lower, upper);
+    }
+    format!(
+        "(?:{}|{})",
+        fancy_regex::escape(&lower),
+        fancy_regex::escape(&upper)
+    )
+}
+
+/// Creates a pattern for a single word
+fn create_word_pattern(word: &str, config: &FuzzyConfig, case_sensitive: bool) -> String {
+    debug!("Creating pattern for word: {}", word);
+    debug!(
+        "Config: max_char_gap={}, min_word_length={}, required_char_ratio={}",
+        config.max_char_gap, config.min_word_length, config.required_char_ratio
+    );
+
+    let (units, required_chars) = split_word(word, config);
+    if let [unit] = units[..] {
+        if config.emoji_aware && unit.chars().next().is_some_and(is_emoji) {
+            let emoji = create_emoji_pattern(unit);
+            debug!("Emoji pattern: {}", emoji);
+            return format!("(?:{})", emoji);
+        }
+    }
+
+    // Words below the minimum length are too short to match fuzzily
+    if word.chars().count() < config.min_word_length {
+        let literal: String = word
+            .chars()
+            .map(|c| {
+                equivalence_pattern(c, config, case_sensitive)
+                    .unwrap_or_else(|| literal_char_pattern(c, case_sensitive))
+            })
+            .collect();
+        debug!("Short word '{}' matched exactly: {}", word, literal);
+        return format!("(?:{})", literal);
+    }
+
+    // Special handling for single character inputs
+    if let [c] = word.chars().collect::<Vec<_>>()[..] {
+        let char_pattern = literal_char_pattern(c, case_sensitive);
+        debug!("Single character pattern: {}", char_pattern);
+        return format!("(?:[^\\s]*?{}[^\\s]*?)", char_pattern);
+    }
+
+    let mut chars: Vec<_> = units
+        .iter()
+        .map(|&unit| {
+            if config.emoji_aware && unit.chars().next().is_some_and(is_emoji) {
+                let emoji = create_emoji_pattern(unit);
+                debug!("Emoji '{}' pattern: {}", unit, emoji);
+                return emoji;
+            }
+            if is_class_unit(unit) {
+                debug!("Character class passed through: {}", unit);
+                return if case_sensitive {
+                    unit.to_string()
+                } else {
+                    format!("(?i:{})", unit)
+                };
+            }
+            let c = unit_char(unit);
+            let c_str = c.to_string();
+            let escaped = fancy_regex::escape(&c_str);
+            if let Some(class) = equivalence_pattern(c, config, case_sensitive) {
+                class
+            } else if c.is_ascii_digit() && config.digits_required {
+                debug!("Required digit '{}'", c);
+                escaped.into_owned()
+            } else if c.is_ascii_punctuation() || c.is_ascii_digit() || !c.is_ascii() {
+                debug!("Special character '{}' escaped as: {}", c, escaped);
+                format!("(?:{})?", escaped)
+            } else if case_sensitive {
+                debug!("Case-sensitive character '{}' escaped as: {}", c, escaped);
+                escaped.into_owned()
+            } else {
+                debug!(
+                    "Case-insensitive character '{}' pattern: [{}{}]",
+                    c,
+                    c.to_lowercase(),
+                    c.to_uppercase()
+                );
+                let lower: String = c.to_lowercase().collect();
+                let upper: String = c.to_uppercase().collect();
+                format!("[{}{}]", lower, upper)
+            }
+        })
+        .collect();
+    debug!("Processed chars: {:?}", chars);
+
+    // Create gap patterns based on configuration, indexed by the character they precede
+    let word_chars: Vec<char> = units.iter().map(|unit| unit_char(unit)).collect();
+    let mut between_patterns: Vec<String> = (0..word_chars.len())
+        .map(|i| match i {
+            0 => String::new(),
+            _ => match &config.custom_gap_pattern {
+                Some(gap) => format!("(?:{})", gap),
+                None => {
+                    create_gap_pattern(pair_gap(word_chars[i - 1], word_chars[i], config), config)
+                }
+            },
+        })
+        .collect();
+    for i in 0..chars.len() {
+        if is_missable_double(&units, i, config) {
+            // The gap goes with the character so a missing double leaves a single gap
+            let gap = std::mem::take(&mut between_patterns[i]);
+            chars[i] = format!("(?:{}{})?", gap, chars[i]);
+            debug!("Doubled character '{}' may be missing", word_chars[i]);
+        }
+    }
+
+    debug!("Between patterns: {:?}", between_patterns);
+
+    if required_chars == chars.len() {
+        debug!(
+            "Using strict pattern requiring all {} characters",
+            required_chars
+        );
+    } else {
+        debug!(
+            "Using flexible pattern with required_char_ratio={}, required_chars={}",
+            config.required_char_ratio, required_chars
+        );
+    }
+    let (required, optional) = chars.split_at(required_chars);
+    let (required_gaps, optional_gaps) = between_patterns.split_at(required_chars);
+
+    let mut char_pattern = String::new();
+    // Add required characters with flexible gaps
+    let sequence = |order: &[&String]| -> String {
+        order
+            .iter()
+            .zip(required_gaps)
+            .map(|(c, between_pattern)| format!("{}{}", between_pattern, c))
+            .collect()
+    };
+    let in_order: Vec<&String> = required.iter().collect();
+    if config.transposition_tolerance && required.len() > 1 {
+        // One alternative per swapped adjacent pair, capped to bound the pattern size
+        let pairs = (required.len() - 1).min(MAX_TRANSPOSITION_PAIRS);
+        let mut alternatives = vec![sequence(&in_order)];
+        for i in 0..pairs {
+            let mut swapped = in_order.clone();
+            swapped.swap(i, i + 1);
+            alternatives.push(sequence(&swapped));
+        }
+        debug!("Allowing {} transpositions", pairs);
+        char_pattern.push_str(&format!("(?:{})", alternatives.join("|")));
+    } else {
+        char_pattern.push_str(&sequence(&in_order));
+    }
+
+    // Add optional characters
+    if !optional.is_empty() {
+        debug!("Adding {} optional characters", optional.len());
+        char_pattern.push_str("(?:");
+        for (i, (c, between_pattern)) in optional.iter().zip(optional_gaps).enumerate() {
+            if i > 0 && config.strict_optional_gaps {
+                // Only allow the gap when the optional character is present
+                char_pattern.push_str(&format!("(?:{}{})?", between_pattern, c));
+                continue;
+            }
+            if i > 0 {
+                char_pattern.push_str(between_pattern);
+            }
+            char_pattern.push_str(&format!("{}?", c));
+        }
+        char_pattern.push_str(")?");
+    }
+
+    // Create the final pattern with appropriate word boundaries
+    let final_pattern = format!("(?:{})", char_pattern);
+    debug!("Final word pattern: {}", final_pattern);
+    final_pattern
+}
+
+/// Builds the pattern of a character that also matches equivalent characters
+///
+/// Covers `leet`, `keyboard_layout`, `ignore_diacritics` and full case
+/// folding, in that order. Returns `None` when none of them applies.
+fn equivalence_pattern(c: char, config: &FuzzyConfig, case_sensitive: bool) -> Option<String> {
+    if let Some(class) = leet_class(c, config, case_sensitive) {
+        debug!("Leetspeak character '{}' pattern: {}", c, class);
+        Some(class)
+    } else if let Some(class) = keyboard_class(c, config, case_sensitive) {
+        debug!("Keyboard neighbors of '{}' pattern: {}", c, class);
+        Some(class)
+    } else if let Some(class) = diacritic_class(c, config, case_sensitive) {
+        debug!("Accented forms of '{}' pattern: {}", c, class);
+        Some(class)
+    } else {
+        let folded = full_fold_pattern(c, config, case_sensitive)?;
+        debug!("Full case folding of '{}' pattern: {}", c, folded);
+        Some(folded)
+    }
+}
+
+/// Checks whether the unit repeats the one before it and `tolerate_missing_doubles` is set
+fn is_missable_double(units: &[&str], i: usize, config: &FuzzyConfig) -> bool {
+    config.tolerate_missing_doubles
+        && i > 0
+        && !is_class_unit(units[i])
+        && units[i].to_lowercase() == units[i - 1].to_lowercase()
+}
+
+/// Splits a word into its units and counts how many leading units are required
+///
+/// Words below `min_word_length` are matched exactly, so all their units are required.
+fn split_word<'a>(word: &'a str, config: &FuzzyConfig) -> (Vec<&'a str>, usize) {
+    let units = split_units(word, config);
+    let required = if word.chars().count() < config.min_word_length {
+        units.len()
+    } else {
+        required_char_count(word, units.len(), config)
+    };
+    // Required digits can't sit in the optional tail
+    let required = match units.iter().rposition(|unit| is_digit_unit(unit)) {
+        Some(last) if config.digits_required => required.max(last + 1),
+        _ => required,
+    };
+    (units, required)
+}
+
+/// Reads a line into `buf` without its line ending, keeping at most `limit` bytes
+///
+/// Returns `None` at the end of input, or whether the line was cut short.
+/// Bytes past the limit are consumed and dropped, so memory stays bounded.
+fn read_bounded_line<R: BufRead>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    limit: usize,
+) -> io::Result<Option<bool>> {
+    let mut read_any = false;
+    let mut truncated = false;
+    loop {
+        let available = match reader.fill_buf() {
+            Ok(available) => available,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if available.is_empty() {
+            break;
+        }
+        read_any = true;
+        let (chunk, consumed, done) = match available.iter().position(|&b| b == b'\n') {
+            Some(i) => (&available[..i], i + 1, true),
+            None => (available, available.len(), false),
+        };
+        let room = limit.saturating_sub(buf.len());
+        truncated |= chunk.len() > room;
+        buf.extend_from_slice(&chunk[..chunk.len().min(room)]);
+        reader.consume(consumed);
+        if done {
+            break;
+        }
+    }
+    if !read_any {
+        return Ok(None);
+    }
+    if buf.last() == Some(&b'\r') {
+        buf.pop();
+    }
+    Ok(Some(truncated))
+}
+
+/// Checks whether the unit is a single ASCII digit
+fn is_digit_unit(unit: &str) -> bool {
+    unit.len() == 1 && unit.as_bytes()[0].is_ascii_digit()
+}
+
+/// Returns how many leading characters of a word must be present
+///
+/// Capitalized words under `strict_proper_nouns` require every character.
+/// `max_typos` lets that many trailing characters go missing; failing that, a
+/// `fuzziness_budget` lets up to that many trailing characters go missing;
+/// otherwise ratios above 0.9 require every character and lower ratios round up.
+fn required_char_count(word: &str, len: usize, config: &FuzzyConfig) -> usize {
+    if config.require_full_word {
+        return len;
+    }
+    if config.strict_proper_nouns && is_proper_noun(word) {
+        debug!("Treating '{}' as a proper noun", word);
+        return len;
+    }
+    // Typo counts and budgets keep at least the first character
+    let required = match (config.max_typos, config.fuzziness_budget) {
+        (Some(typos), _) => len.saturating_sub(typos).max(1),
+        (None, Some(budget)) => len.saturating_sub(budget as usize).max(1),
+        (None, None) if config.required_char_ratio > 0.9 => len,
+        (None, None) => {
+            let exact = len as f32 * config.required_char_ratio;
+            match config.ratio_rounding {
+                RatioRounding::Ceil => exact.ceil() as usize,
+                RatioRounding::Floor => exact.floor() as usize,
+                RatioRounding::Round => exact.round() as usize,
+            }
+        }
+    };
+    // Never require more characters than the word has
+    required.min(len)
+}
+
+/// Checks that a gap fragment stays self-contained when spliced into a word pattern
+///
+/// Fails if the fragment closes a group it didn't open, alternates at the top
+/// level, or has a capturing group, which would shift the capture indices
+/// spans are read from. Escapes and character classes are skipped.
+fn check_gap_structure(gap: &str) -> Result<(), &'static str> {
+    let chars: Vec<char> = gap.chars().collect();
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '[' => {
+                // A `]` right after the opening bracket (or `[^`) is a literal
+                i += 1;
+                if chars.get(i) == Some(&'^') {
+                    i += 1;
+                }
+                if chars.get(i) == Some(&']') {
+                    i += 1;
+                }
+                while i < chars.len() && chars[i] != ']' {
+                    if chars[i] == '\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+            }
+            '(' => {
+                let rest: String = chars[i + 1..].iter().take(3).collect();
+                let named =
+                    rest.starts_with("?<") && !rest.starts_with("?<=") && !rest.starts_with("?<!")
+                        || rest.starts_with("?P<");
+                if !rest.starts_with('?') || named {
+                    return Err("has a capturing group");
+                }
+                depth += 1;
+            }
+            ')' if depth == 0 => return Err("closes a group it didn't open"),
+            ')' => depth -= 1,
+            '|' if depth == 0 => return Err("alternates outside a group"),
+            _ => {}
+        }
+        i += 1;
+    }
+    Ok(())
+}
+
+/// Gap above which whitespace is allowed when `gap_allows_whitespace` is unset
+const LEGACY_WHITESPACE_GAP: usize = 10;
+
+/// Creates the pattern allowed between two adjacent characters of a word
+fn create_gap_pattern(max_char_gap: usize, config: &FuzzyConfig) -> String {
+    let allows_whitespace = config
+        .gap_allows_whitespace
+        .unwrap_or(max_char_gap > LEGACY_WHITESPACE_GAP);
+    if max_char_gap > 0 {
+        // When max_char_gap is set, allow any characters within the limit
+        if allows_whitespace {
+            // For large gaps, allow any characters including spaces
+            debug!("Using large gap pattern with max_char_gap={}", max_char_gap);
+            format!(".{{0,{}}}", max_char_gap)
+        } else {
+            // For small gaps, only allow non-space characters
+            debug!("Using small gap pattern with max_char_gap={}", max_char_gap);
+            format!("[^\\s]{{0,{}}}", max_char_gap)
+        }
+    } else {
+        // When max_char_gap is 0, don't allow any characters between
+        debug!("Using zero gap pattern");
+        "".to_string()
+    }
+}
+
+/// Returns the gap allowed between two adjacent characters
+///
+/// Each character's category may override `max_char_gap` through
+/// `gap_by_category`; the more permissive of the two gaps applies.
+fn pair_gap(prev: char, next: char, config: &FuzzyConfig) -> usize {
+    let gap_for = |c: char| {
+        config
+            .gap_by_category
+            .get(&CharCategory::of(c))
+            .copied()
+            .unwrap_or_else(|| config.effective_max_char_gap())
+    };
+    gap_for(prev).max(gap_for(next))
+}
+
+/// Leetspeak substitutions for letters
+const LEET_TABLE: &[(char, &str)] = &[
+    ('a', "4@"),
+    ('e', "3"),
+    ('i', "1"),
+    ('l', "1"),
+    ('o', "0"),
+    ('s', "5"),
+    ('t', "7"),
+];
+
+/// Characters whose full case folding expands to several characters
+const FULL_CASE_FOLDS: &[(char, &str)] = &[
+    ('ß', "ss"),
+    ('ẞ', "ss"),
+    ('ﬀ', "ff"),
+    ('ﬁ', "fi"),
+    ('ﬂ', "fl"),
+    ('ﬃ', "ffi"),
+    ('ﬄ', "ffl"),
+    ('ﬅ', "st"),
+    ('ﬆ', "st"),
+    ('ŉ', "ʼn"),
+];
+
+/// Patterns for the Turkish dotted and dotless I and their Latin counterparts
+///
+/// Case-insensitivity is turned off inside, since "I" would otherwise also
+/// match "i" where only "ı" is meant.
+const TURKISH_I_FOLDS: &[(char, &str)] = &[
+    ('i', "(?-i:[iIİ])"),
+    ('I', "(?-i:[iIı])"),
+    ('İ', "(?-i:İ|i\\x{307}?)"),
+    ('ı', "(?-i:[ıI])"),
+];
+
+/// Builds a pattern matching a character or its full case folding
+fn full_fold_pattern(c: char, config: &FuzzyConfig, case_sensitive: bool) -> Option<String> {
+    if config.case_fold != CaseFold::Full || case_sensitive {
+        return None;
+    }
+    if let Some((_, pattern)) = TURKISH_I_FOLDS.iter().find(|(from, _)| *from == c) {
+        return Some(pattern.to_string());
+    }
+    let (_, folded) = FULL_CASE_FOLDS.iter().find(|(from, _)| *from == c)?;
+    let folded: String = folded
+        .chars()
+        .map(|c| literal_char_pattern(c, false))
+        .collect();
+    Some(format!(
+        "(?:{}|{})",
+        fancy_regex::escape(&c.to_string()),
+        folded
+    ))
+}
+
+/// Keys physically adjacent to each letter on a QWERTY keyboard
+const QWERTY_ADJACENCY: &[(char, &str)] = &[
+    ('q', "wa"),
+    ('w', "qeas"),
+    ('e', "wrsd"),
+    ('r', "etdf"),
+    ('t', "ryfg"),
+    ('y', "tugh"),
+    ('u', "yihj"),
+    ('i', "uojk"),
+    ('o', "ipkl"),
+    ('p', "ol"),
+    ('a', "qwsz"),
+    ('s', "weadzx"),
+    ('d', "erfsxc"),
+    ('f', "rtgdcv"),
+    ('g', "tyhfvb"),
+    ('h', "yujgbn"),
+    ('j', "uikhnm"),
+    ('k', "iojlm"),
+    ('l', "opk"),
+    ('z', "asx"),
+    ('x', "zsdc"),
+    ('c', "xdfv"),
+    ('v', "cfgb"),
+    ('b', "vghn"),
+    ('n', "bhjm"),
+    ('m', "njk"),
+];
+
+/// Builds a character class covering a letter and its adjacent keys
+fn keyboard_class(c: char, config: &FuzzyConfig, case_sensitive: bool) -> Option<String> {
+    let neighbors = config.keyboard_layout?.neighbors(c);
+    if neighbors.is_empty() {
+        return None;
+    }
+    let mut class = String::from("[");
+    for key in std::iter::once(c).chain(neighbors.chars()) {
+        if case_sensitive && key == c {
+            class.push(c);
+        } else {
+            class.push(key.to_ascii_lowercase());
+            class.push(key.to_ascii_uppercase());
+        }
+    }
+    class.push(']');
+    Some(class)
+}
+
+/// Accented Latin letters by their base letter
+const DIACRITIC_TABLE: &[(char, &str)] = &[
+    ('a', "àáâãäåāăą"),
+    ('c', "çćĉċč"),
+    ('d', "ďđ"),
+    ('e', "èéêëēĕėęě"),
+    ('g', "ĝğġģ"),
+    ('h', "ĥħ"),
+    ('i', "ìíîïĩīĭįı"),
+    ('j', "ĵ"),
+    ('k', "ķ"),
+    ('l', "ĺļľŀł"),
+    ('n', "ñńņňŉ"),
+    ('o', "òóôõöøōŏő"),
+    ('r', "ŕŗř"),
+    ('s', "śŝşš"),
+    ('t', "ţťŧ"),
+    ('u', "ùúûüũūŭůűų"),
+    ('w', "ŵ"),
+    ('y', "ýÿŷ"),
+    ('z', "źżž"),
+];
+
+/// Builds a character class covering a letter and its accented forms
+fn diacritic_class(c: char, config: &FuzzyConfig, case_sensitive: bool) -> Option<String> {
+    if !config.ignore_diacritics {
+        return None;
+    }
+    let lower = c.to_ascii_lowercase();
+    let (_, accented) = DIACRITIC_TABLE.iter().find(|(base, _)| *base == lower)?;
+    let upper = |c: char| {
+        let mut upper = c.to_uppercase();
+        match (upper.next(), upper.next()) {
+            (Some(u), None) if u != c => Some(u),
+            _ => None,
+        }
+    };
+    let mut class = String::from("[");
+    for letter in std::iter::once(lower).chain(accented.chars()) {
+        if !case_sensitive || c.is_lowercase() {
+            class.push(letter);
+        }
+        if !case_sensitive || c.is_uppercase() {
+            class.extend(upper(letter));
+        }
+    }
+    class.push(']');
+    Some(class)
+}
+
+/// Checks whether the character is a combining diacritical mark
+fn is_combining_mark(c: char) -> bool {
+    matches!(c as u32, 0x0300..=0x036F)
+}
+
+/// Returns the base letter of an accented Latin letter, keeping its case
+fn fold_diacritic(c: char) -> char {
+    let lower = c.to_lowercase().next().unwrap_or(c);
+    let Some(&(base, _)) = DIACRITIC_TABLE
+        .iter()
+        .find(|(_, accented)| accented.contains(lower))
+    else {
+        return c;
+    };
+    if c.is_uppercase() {
+        base.to_ascii_uppercase()
+    } else {
+        base
+    }
+}
+
+/// Checks whether two characters are leetspeak equivalents
+fn leet_equivalent(a: char, b: char) -> bool {
+    let group = |c: char| {
+        let lower = c.to_ascii_lowercase();
+        LEET_TABLE
+            .iter()
+            .filter(move |(letter, subs)| *letter == lower || subs.contains(c))
+            .map(|(letter, _)| *letter)
+    };
+    group(a).any(|letter| group(b).any(|other| other == letter))
+}
+
+/// Builds a character class covering a character and its leetspeak equivalents
+fn leet_class(c: char, config: &FuzzyConfig, case_sensitive: bool) -> Option<String> {
+    if !config.leet {
+        return None;
+    }
+    let lower = c.to_ascii_lowercase();
+    let letters: Vec<char> = LEET_TABLE
+        .iter()
+        .filter(|(letter, subs)| *letter == lower || subs.contains(c))
+        .map(|(letter, _)| *letter)
+        .collect();
+    if letters.is_empty() {
+        return None;
+    }
+
+    let mut class = String::from("[");
+    for letter in &letters {
+        if case_sensitive && *letter == lower {
+            class.push(c);
+        } else {
+            class.push(*letter);
+            class.push(letter.to_ascii_uppercase());
+        }
+    }
+    for (_, subs) in LEET_TABLE
+        .iter()
+        .filter(|(letter, _)| letters.contains(letter))
+    {
+        for sub in subs.chars() {
+            if !class.contains(sub) {
+                class.push(sub);
+            }
+        }
+    }
+    class.push(']');
+    Some(class)
+}
+
+/// Returns true if the word starts with an uppercase letter
+fn is_proper_noun(word: &str) -> bool {
+    word.chars().next().is_some_and(|c| c.is_uppercase())
+}
+
+/// Fuzzy matching methods on string slices using the default configuration
+///
+/// ```
+/// use fuzzy_search::FuzzyMatchExt;
+///
+/// assert!("hello world".fuzzy_matches("helo"));
+/// ```
+pub trait FuzzyMatchExt {
+    /// Checks whether the query fuzzily matches this text
+    fn fuzzy_matches(&self, query: &str) -> bool;
+
+    /// Scores this text against the query, returning `None` when it doesn't match
+    fn fuzzy_score(&self, query: &str) -> Option<f32>;
+}
+
+impl FuzzyMatchExt for str {
+    fn fuzzy_matches(&self, query: &str) -> bool {
+        FuzzyConfig::builder()
+            .search_term(query)
+            .build()
+            .is_match(self)
+            .unwrap_or(false)
+    }
+
+    fn fuzzy_score(&self, query: &str) -> Option<f32> {
+        FuzzyConfig::builder()
+            .search_term(query)
+            .build()
+            .score(self)
+            .ok()
+            .flatten()
+    }
+}
+
+/// Simplified function for quick fuzzy pattern generation with default settings
+///
+/// Returns an empty pattern, which matches everything, if the pattern can't
+/// be built. Prefer [`try_fuzzy_search_pattern`] to handle that case.
+pub fn fuzzy_search_pattern(search_term: &str) -> String {
+    try_fuzzy_search_pattern(search_term).unwrap_or_else(|_| "".to_string())
+}
+
+/// Generates a fuzzy pattern with default settings, failing on invalid terms
+pub fn try_fuzzy_search_pattern(search_term: &str) -> Result<String, FuzzyError> {
+    FuzzyConfig::new(search_term).build_pattern()
+}
+
+/// Splits a word into the characters its pattern requires and those it makes optional
+///
+/// Uses the same split as pattern generation. Character classes and emoji
+/// sequences count as one character, represented by their first character.
+/// Punctuation, digits and non-ASCII characters are optional in the pattern
+/// wherever they fall, but are listed by position here.
+pub fn analyze_word(word: &str, config: &FuzzyConfig) -> WordAnalysis {
+    let (units, required_chars) = split_word(word, config);
+    let chars: Vec<char> = units.iter().map(|unit| unit_char(unit)).collect();
+    let (required, optional) = chars.split_at(required_chars);
+    WordAnalysis {
+        required: required.to_vec(),
+        optional: optional.to_vec(),
+        required_chars,
+    }
+}
+
+/// Aligns the term's match units in order with the characters of a matched span
+///
+/// Returns the index of each aligned unit with how its character matched.
+/// Units without a matching character are left out.
+fn align_units(
+    config: &FuzzyConfig,
+    haystack: &str,
+    span: Range<usize>,
+) -> Vec<(usize, CharMatch)> {
+    let case_sensitive = config.is_case_sensitive();
+    let classify = |query: char, found: char| {
+        let fold = |c: char| -> String {
+            if case_sensitive {
+                c.to_string()
+            } else {
+                c.to_lowercase().collect()
+            }
+        };
+        if query == found {
+            Some(CharMatchKind::Exact)
+        } else if fold(query) == fold(found) {
+            Some(CharMatchKind::CaseFold)
+        } else if fold(fold_diacritic(query)) == fold(fold_diacritic(found)) {
+            Some(CharMatchKind::DiacriticFold)
+        } else if config.leet && leet_equivalent(query, found)
+            || config
+                .keyboard_layout
+                .is_some_and(|layout| layout.neighbors(query).contains(found.to_ascii_lowercase()))
+        {
+            Some(CharMatchKind::Equivalence)
+        } else {
+            None
+        }
+    };
+
+    let matched: Vec<(usize, char)> = haystack[span.clone()]
+        .char_indices()
+        .map(|(i, c)| (span.start + i, c))
+        .collect();
+    let mut next = 0;
+    let mut aligned = Vec::new();
+    for (unit_index, unit) in config
+        .match_units(&config.search_term)
+        .into_iter()
+        .enumerate()
+    {
+        if is_class_unit(unit) {
+            let flag = if case_sensitive { "" } else { "(?i)" };
+            let Ok(class) = fancy_regex::Regex::new(&format!("{}^{}$", flag, unit)) else {
+                continue;
+            };
+            let found = matched[next..]
+                .iter()
+                .enumerate()
+                .find(|(_, &(_, c))| class.is_match(c.encode_utf8(&mut [0; 4])).unwrap_or(false));
+            if let Some((i, &(position, c))) = found {
+                aligned.push((
+                    unit_index,
+                    CharMatch {
+                        query: c,
+                        position,
+                        kind: CharMatchKind::Exact,
+                    },
+                ));
+                next += i + 1;
+            }
+            continue;
+        }
+        for query in unit.chars().filter(|c| !c.is_whitespace()) {
+            let found = matched[next..]
+                .iter()
+                .enumerate()
+                .find_map(|(i, &(position, c))| classify(query, c).map(|kind| (i, position, kind)));
+            if let Some((i, position, kind)) = found {
+                aligned.push((
+                    unit_index,
+                    CharMatch {
+                        query,
+                        position,
+                        kind,
+                    },
+                ));
+                next += i + 1;
+            }
+        }
+    }
+    aligned
+}
+
+/// Escapes whitespace and `#` in a pattern so it means the same in extended mode
+///
+/// Escape sequences are copied as they are, since an escaped space is already
+/// literal in extended mode.
+fn escape_extended(pattern: &str) -> String {
+    let mut escaped = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                escaped.push(c);
+                escaped.extend(chars.next());
+            }
+            c if c.is_whitespace() || c == '#' => {
+                escaped.push_str(&format!("\\x{{{:X}}}", c as u32));
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Returns the smallest range covering all the spans, or `None` if there are none
+fn spans_union(spans: &[Range<usize>]) -> Option<Range<usize>> {
+    spans
+        .iter()
+        .cloned()
+        .reduce(|a, b| a.start.min(b.start)..a.end.max(b.end))
+}
+
+/// Coalesces spans separated by at most `gap` bytes
+///
+/// Spans may be given in any order and may overlap; the result is sorted.
+pub fn merge_spans(spans: &[Range<usize>], gap: usize) -> Vec<Range<usize>> {
+    let mut sorted = spans.to_vec();
+    sorted.sort_by_key(|span| (span.start, span.end));
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(sorted.len());
+    for span in sorted {
+        match merged.last_mut() {
+            Some(last) if span.start <= last.end + gap => last.end = last.end.max(span.end),
+            _ => merged.push(span),
+        }
+    }
+    merged
+}
+
+/// Scores how similar `b` is to `a` with default settings
+///
+/// Uses `a` as the search term and returns the score of `b`, or 0.0 when
+/// `b` doesn't match or `a` is empty.
+pub fn fuzzy_similarity(a: &str, b: &str) -> f32 {
+    b.fuzzy_score(a).unwrap_or(0.0)
 }
 
 #[cfg(test)]
@@ -308,299 +4606,3015 @@ mod tests {
     use fancy_regex::Regex;
 
     #[test]
-    fn test_single_word_pattern() {
-        let pattern = FuzzyConfig::builder()
-            .search_term("hello")
-            .build()
-            .build_pattern()
-            .unwrap();
-        let regex = Regex::new(&pattern).unwrap();
+    fn test_single_word_pattern() {
+        let pattern = FuzzyConfig::builder()
+            .search_term("hello")
+            .build()
+            .build_pattern()
+            .unwrap();
+        let regex = Regex::new(&pattern).unwrap();
+
+        assert!(regex.is_match("hello").unwrap());
+        assert!(regex.is_match("HELLO").unwrap());
+        assert!(regex.is_match("hello world").unwrap());
+        assert!(regex.is_match("say hello there").unwrap());
+        assert!(regex.is_match("heeello").unwrap()); // with extra chars
+    }
+
+    #[test]
+    fn test_multi_word_pattern() {
+        let pattern = FuzzyConfig::builder()
+            .search_term("hello world")
+            .build()
+            .build_pattern()
+            .unwrap();
+        let regex = Regex::new(&pattern).unwrap();
+
+        assert!(regex.is_match("hello world").unwrap());
+        assert!(regex.is_match("HELLO WORLD").unwrap());
+        assert!(regex.is_match("hello there world").unwrap());
+        assert!(regex.is_match("My hello to the world").unwrap());
+        assert!(!regex.is_match("hello").unwrap()); // missing second word
+        assert!(!regex.is_match("world").unwrap()); // missing first word
+    }
+
+    #[test]
+    fn test_short_word_pattern() {
+        let pattern = FuzzyConfig::builder()
+            .search_term("hi")
+            .build()
+            .build_pattern()
+            .unwrap();
+        let regex = Regex::new(&pattern).unwrap();
+
+        assert!(regex.is_match("hi").unwrap());
+        assert!(regex.is_match("HI").unwrap());
+        assert!(regex.is_match("this").unwrap());
+        assert!(regex.is_match("history").unwrap());
+        assert!(regex.is_match("hi there").unwrap());
+    }
+
+    #[test]
+    fn test_long_word_pattern() {
+        let pattern = FuzzyConfig::builder()
+            .search_term("programming")
+            .build()
+            .build_pattern()
+            .unwrap();
+        let regex = Regex::new(&pattern).unwrap();
+
+        assert!(regex.is_match("programming").unwrap());
+        assert!(regex.is_match("PROGRAMMING").unwrap());
+        assert!(regex.is_match("programmming").unwrap()); // typo
+        assert!(regex.is_match("program").unwrap()); // partial match is ok
+    }
+
+    #[test]
+    fn test_case_sensitivity() {
+        let pattern = FuzzyConfig::builder()
+            .search_term("Test")
+            .case_sensitive(true)
+            .build()
+            .build_pattern()
+            .unwrap();
+        let regex = Regex::new(&pattern).unwrap();
+
+        assert!(regex.is_match("Test").unwrap());
+        assert!(!regex.is_match("test").unwrap());
+        assert!(!regex.is_match("TEST").unwrap());
+        assert!(!regex.is_match("testing").unwrap());
+    }
+
+    #[test]
+    fn test_custom_config() {
+        let pattern = FuzzyConfig::builder()
+            .search_term("hello")
+            .min_word_length(5)
+            .required_char_ratio(0.8)
+            .max_char_gap(2)
+            .build()
+            .build_pattern()
+            .unwrap();
+        println!("Generated pattern: {}", pattern);
+        let regex = Regex::new(&pattern).unwrap();
+
+        assert!(regex.is_match("hello").unwrap());
+        assert!(regex.is_match("heello").unwrap()); // small gap
+        assert!(!regex.is_match("h e l l o").unwrap()); // too big gaps
+
+        // Debug prints for failing case
+        println!("Testing 'h e l l o' against pattern");
+        println!("Pattern matches: {}", regex.is_match("h e l l o").unwrap());
+    }
+
+    #[test]
+    fn test_empty_pattern() {
+        let result = FuzzyConfig::builder()
+            .search_term("")
+            .build()
+            .build_pattern();
+        assert!(matches!(result, Err(FuzzyError::EmptyPattern)));
+    }
+
+    #[test]
+    fn test_whitespace_only_pattern() {
+        let result = FuzzyConfig::builder()
+            .search_term("   ")
+            .build()
+            .build_pattern();
+        assert!(matches!(result, Err(FuzzyError::EmptyPattern)));
+    }
+
+    #[test]
+    fn test_builder_methods() {
+        let config = FuzzyConfig::builder()
+            .search_term("test")
+            .min_word_length(4)
+            .required_char_ratio(0.75)
+            .case_sensitive(true)
+            .max_char_gap(3)
+            .build();
+
+        let pattern = config.build_pattern().unwrap();
+        let regex = Regex::new(&pattern).unwrap();
+
+        assert!(regex.is_match("test").unwrap());
+        assert!(!regex.is_match("TEST").unwrap()); // case sensitive
+    }
+
+    #[test]
+    fn test_special_characters() {
+        let pattern = FuzzyConfig::builder()
+            .search_term("hello.world$^")
+            .build()
+            .build_pattern()
+            .unwrap();
+        let regex = Regex::new(&pattern).unwrap();
+
+        assert!(regex.is_match("hello.world$^").unwrap());
+        assert!(regex.is_match("hello world").unwrap()); // Still matches without special chars
+        assert!(regex.is_match("hello...world").unwrap()); // Matches with extra dots
+    }
+
+    #[test]
+    fn test_unicode_characters() {
+        let pattern = FuzzyConfig::builder()
+            .search_term("привет мир")
+            .build()
+            .build_pattern()
+            .unwrap();
+        let regex = Regex::new(&pattern).unwrap();
+
+        assert!(regex.is_match("привет мир").unwrap());
+        assert!(regex.is_match("ПРИВЕТ МИР").unwrap());
+        assert!(regex.is_match("привет добрый мир").unwrap());
+    }
+
+    #[test]
+    fn test_extreme_char_gaps() {
+        let pattern = FuzzyConfig::builder()
+            .search_term("test")
+            .max_char_gap(100)
+            .build()
+            .build_pattern()
+            .unwrap();
+        let regex = Regex::new(&pattern).unwrap();
+
+        assert!(regex.is_match("t e s t").unwrap());
+        assert!(regex.is_match("t....e....s....t").unwrap());
+
+        // Test with minimum gap
+        let pattern = FuzzyConfig::builder()
+            .search_term("test")
+            .max_char_gap(0)
+            .build()
+            .build_pattern()
+            .unwrap();
+        let regex = Regex::new(&pattern).unwrap();
+
+        assert!(regex.is_match("test").unwrap());
+        assert!(!regex.is_match("t e s t").unwrap());
+    }
+
+    #[test]
+    fn test_extreme_word_lengths() {
+        // Very short word
+        let pattern = FuzzyConfig::builder()
+            .search_term("a")
+            .build()
+            .build_pattern()
+            .unwrap();
+        let regex = Regex::new(&pattern).unwrap();
+
+        assert!(regex.is_match("a").unwrap());
+        assert!(regex.is_match("abc").unwrap());
+
+        // Very long word
+        let long_word = "a".repeat(100);
+        let pattern = FuzzyConfig::builder()
+            .search_term(&long_word)
+            .build()
+            .build_pattern()
+            .unwrap();
+        let regex = Regex::new(&pattern).unwrap();
+
+        assert!(regex.is_match(&long_word).unwrap());
+        assert!(regex.is_match(&format!("{}b", &long_word)).unwrap());
+    }
+
+    #[test]
+    fn test_boundary_char_ratio() {
+        // Test with 100% ratio
+        let pattern = FuzzyConfig::builder()
+            .search_term("test")
+            .required_char_ratio(1.0)
+            .build()
+            .build_pattern()
+            .unwrap();
+        let regex = Regex::new(&pattern).unwrap();
+
+        assert!(regex.is_match("test").unwrap());
+        assert!(!regex.is_match("tes").unwrap()); // Won't match with missing char
+
+        // Test with minimum ratio
+        let pattern = FuzzyConfig::builder()
+            .search_term("test")
+            .required_char_ratio(0.0)
+            .build()
+            .build_pattern()
+            .unwrap();
+        let regex = Regex::new(&pattern).unwrap();
+
+        assert!(regex.is_match("test").unwrap());
+        assert!(regex.is_match("t").unwrap()); // Matches with just first char
+    }
+
+    #[test]
+    fn test_multiple_spaces() {
+        let pattern = FuzzyConfig::builder()
+            .search_term("hello   world") // Multiple spaces
+            .build()
+            .build_pattern()
+            .unwrap();
+        let regex = Regex::new(&pattern).unwrap();
+
+        assert!(regex.is_match("hello world").unwrap());
+        assert!(regex.is_match("hello   world").unwrap());
+        assert!(regex.is_match("hello \t\n world").unwrap()); // Different whitespace
+    }
+
+    #[test]
+    fn test_numbers_and_mixed_content() {
+        let pattern = FuzzyConfig::builder()
+            .search_term("test123 456")
+            .build()
+            .build_pattern()
+            .unwrap();
+        let regex = Regex::new(&pattern).unwrap();
+
+        assert!(regex.is_match("test123 456").unwrap());
+        assert!(regex.is_match("test 123 456").unwrap());
+        assert!(regex.is_match("TEST123 456").unwrap());
+    }
+
+    #[test]
+    fn test_logging() {
+        use env_logger;
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        let pattern = FuzzyConfig::builder()
+            .search_term("test")
+            .max_char_gap(2)
+            .min_word_length(3)
+            .required_char_ratio(0.8)
+            .build()
+            .build_pattern()
+            .unwrap();
+
+        let regex = Regex::new(&pattern).unwrap();
+        assert!(regex.is_match("test").unwrap());
+
+        // Test error logging
+        let result = FuzzyConfig::builder()
+            .search_term("")
+            .build()
+            .build_pattern();
+        assert!(matches!(result, Err(FuzzyError::EmptyPattern)));
+    }
+
+    #[test]
+    fn test_backtracking_risk() {
+        let config = FuzzyConfig::builder().search_term("hello").build();
+        assert_eq!(config.backtracking_risk(), RiskLevel::Low);
+
+        let config = FuzzyConfig::builder()
+            .search_term("quick brown foxes jumping over lazy dogs")
+            .max_char_gap(50)
+            .build();
+        assert!(config.backtracking_risk() > RiskLevel::Low);
+    }
+
+    #[test]
+    fn test_strict_proper_nouns() {
+        let fuzzy = FuzzyConfig::builder()
+            .search_term("visit Paris")
+            .build()
+            .compile()
+            .unwrap();
+        assert!(fuzzy.is_match("visiting Par").unwrap());
+
+        let strict = FuzzyConfig::builder()
+            .search_term("visit Paris")
+            .strict_proper_nouns(true)
+            .build()
+            .compile()
+            .unwrap();
+        assert!(strict.is_match("visit Paris").unwrap());
+        assert!(strict.is_match("vis Paris").unwrap()); // "visit" stays fuzzy
+        assert!(!strict.is_match("visiting Par").unwrap()); // "Paris" must be complete
+    }
+
+    #[test]
+    fn test_match_records() {
+        struct User {
+            name: String,
+        }
+
+        let users = vec![
+            User {
+                name: "Alice Cooper".to_string(),
+            },
+            User {
+                name: "Bob".to_string(),
+            },
+            User {
+                name: "alicia".to_string(),
+            },
+            User {
+                name: "Alice".to_string(),
+            },
+        ];
+
+        let matcher =
+            CompiledFuzzy::new(FuzzyConfig::builder().search_term("alice").build()).unwrap();
+        assert!(matcher.match_record(&users[0], |u| u.name.clone()));
+        assert!(!matcher.match_record(&users[1], |u| u.name.clone()));
+
+        // Exact matches come first in their original order, "Bob" is filtered out
+        let ranked = matcher.rank_records(&users, |u| u.name.clone());
+        assert_eq!(ranked, vec![0, 3, 2]);
+    }
+
+    #[test]
+    fn test_leet() {
+        let regex = FuzzyConfig::builder()
+            .search_term("leet")
+            .required_char_ratio(1.0)
+            .max_char_gap(0)
+            .leet(true)
+            .build()
+            .compile()
+            .unwrap();
+        assert!(regex.is_match("leet").unwrap());
+        assert!(regex.is_match("l33t").unwrap());
+        assert!(regex.is_match("1337").unwrap());
+        assert!(regex.is_match("L3E7").unwrap());
+        assert!(!regex.is_match("lxxt").unwrap());
+
+        let regex = FuzzyConfig::builder()
+            .search_term("h4x0r")
+            .required_char_ratio(1.0)
+            .leet(true)
+            .build()
+            .compile()
+            .unwrap();
+        assert!(regex.is_match("haxor").unwrap());
+        assert!(regex.is_match("h@x0r").unwrap());
+    }
+
+    #[test]
+    fn test_minimal_match() {
+        let config = FuzzyConfig::builder().search_term("hello").build();
+        // Trailing optional characters are trimmed once the required ones match
+        assert_eq!(config.minimal_match("say heeello there"), Some("heeel"));
+        assert_eq!(config.minimal_match("nothing here"), None);
+
+        let config = FuzzyConfig::builder().search_term("a").build();
+        let snippet = config.minimal_match("banana").unwrap();
+        assert_eq!(snippet, "a");
+        assert!(config.compile().unwrap().is_match(snippet).unwrap());
+
+        let config = FuzzyConfig::builder().search_term("hello world").build();
+        let snippet = config.minimal_match("oh, hello big world!").unwrap();
+        assert_eq!(snippet, "hello big wor");
+        assert!(config.compile().unwrap().is_match(snippet).unwrap());
+    }
+
+    #[test]
+    fn test_snippet() {
+        let config = FuzzyConfig::builder()
+            .search_term("fox")
+            .required_char_ratio(1.0)
+            .build();
+        let text = "the quick brown fox jumps over the lazy dog";
+
+        assert_eq!(config.snippet(text, 4).unwrap(), "…own fox jum…");
+        assert_eq!(config.snippet(text, 0).unwrap(), "…fox…");
+        assert_eq!(config.snippet(text, 100).unwrap(), text);
+        assert_eq!(config.snippet("fox den", 2).unwrap(), "fox d…"); // clamped at start
+        assert_eq!(config.snippet("red fox", 2).unwrap(), "…d fox"); // clamped at end
+        assert_eq!(config.snippet("no match", 2), None);
+
+        // Combining marks stay attached to their base character
+        assert_eq!(
+            config.snippet("cafe\u{301} fox", 2).unwrap(),
+            "…e\u{301} fox"
+        );
+    }
+
+    #[test]
+    fn test_strict_optional_gaps() {
+        let loose = FuzzyConfig::builder()
+            .search_term("program x")
+            .build()
+            .compile()
+            .unwrap();
+        let strict = FuzzyConfig::builder()
+            .search_term("program x")
+            .strict_optional_gaps(true)
+            .build()
+            .compile()
+            .unwrap();
+
+        for haystack in ["program x", "prog x", "progam x", "progrm x"] {
+            assert!(loose.is_match(haystack).unwrap());
+            assert!(strict.is_match(haystack).unwrap());
+        }
+        // A gap can no longer stand in for the skipped "ram"
+        assert!(loose.is_match("progzzz x").unwrap());
+        assert!(!strict.is_match("progzzz x").unwrap());
+    }
+
+    #[test]
+    fn test_gap_by_category() {
+        let tight = FuzzyConfig::builder()
+            .search_term("ab12 x")
+            .required_char_ratio(1.0)
+            .max_char_gap(0)
+            .build()
+            .compile()
+            .unwrap();
+        assert!(tight.is_match("ab12 x").unwrap());
+        assert!(!tight.is_match("ab--12 x").unwrap());
+
+        let regex = FuzzyConfig::builder()
+            .search_term("ab12 x")
+            .required_char_ratio(1.0)
+            .max_char_gap(0)
+            .gap_by_category(HashMap::from([(CharCategory::Digit, 5)]))
+            .build()
+            .compile()
+            .unwrap();
+        assert!(regex.is_match("ab12 x").unwrap());
+        assert!(regex.is_match("ab--12 x").unwrap()); // wide gap around digits
+        assert!(!regex.is_match("a--b12 x").unwrap()); // letters stay tight
+    }
+
+    #[test]
+    fn test_max_words_between() {
+        let regex = FuzzyConfig::builder()
+            .search_term("hello world")
+            .max_words_between(1)
+            .build()
+            .compile()
+            .unwrap();
+        assert!(regex.is_match("hello world").unwrap());
+        assert!(regex.is_match("hello big world").unwrap());
+        assert!(regex.is_match("hello big theworld").unwrap()); // still a substring match
+        assert!(!regex.is_match("hello big bad world").unwrap());
+
+        let regex = FuzzyConfig::builder()
+            .search_term("hello world")
+            .max_words_between(0)
+            .build()
+            .compile()
+            .unwrap();
+        assert!(regex.is_match("hello \t world").unwrap());
+        assert!(!regex.is_match("hello big world").unwrap());
+    }
+
+    #[test]
+    fn test_rank_by_position() {
+        let config = FuzzyConfig::builder()
+            .search_term("fox")
+            .required_char_ratio(1.0)
+            .build();
+        let candidates = [
+            "the quick brown fox",
+            "fox",
+            "a fox",
+            "no match here",
+            "one fox",
+        ];
+
+        let positions = config.rank_by_position(&candidates).unwrap();
+        assert_eq!(positions, vec![(1, 0), (2, 2), (4, 4), (0, 16)]);
+
+        // Score ranking orders the same candidates differently
+        let ranked: Vec<usize> = config
+            .rank(&candidates)
+            .unwrap()
+            .into_iter()
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(ranked, vec![0, 1, 2, 4]);
+    }
+
+    #[test]
+    fn test_distinct_word_positions() {
+        let shared = FuzzyConfig::builder()
+            .search_term("test test")
+            .ignore_word_order(true)
+            .build();
+        assert!(shared.is_match("test").unwrap());
+
+        let distinct = FuzzyConfig::builder()
+            .search_term("test test")
+            .ignore_word_order(true)
+            .distinct_word_positions(true)
+            .build();
+        assert!(!distinct.is_match("test").unwrap());
+        assert!(distinct.is_match("test and test").unwrap());
+        assert!(distinct.is_match("a TEST, then a test").unwrap());
+    }
+
+    #[test]
+    fn test_edit_script() {
+        let config = FuzzyConfig::builder().search_term("hello").build();
+
+        assert_eq!(config.edit_script("HELLO"), Some(vec![]));
+        assert_eq!(
+            config.edit_script("say helpo"),
+            Some(vec![EditOp::Substitute {
+                position: 3,
+                from: 'l',
+                to: 'p'
+            }])
+        );
+
+        let ops = config.edit_script("heello").unwrap();
+        assert_eq!(ops.len(), 1);
+        assert!(matches!(ops[0], EditOp::Insert { ch: 'e', .. }));
+
+        assert_eq!(config.edit_script("nothing"), None);
+    }
+
+    #[test]
+    fn test_fuzziness_budget() {
+        let exact = FuzzyConfig::builder()
+            .search_term("hello")
+            .fuzziness_budget(0)
+            .build()
+            .compile()
+            .unwrap();
+        assert!(exact.is_match("hello").unwrap());
+        assert!(!exact.is_match("heello").unwrap());
+        assert!(!exact.is_match("hell").unwrap());
+
+        let regex = FuzzyConfig::builder()
+            .search_term("program")
+            .fuzziness_budget(2)
+            .build()
+            .compile()
+            .unwrap();
+        assert!(regex.is_match("program").unwrap());
+        assert!(regex.is_match("progr").unwrap()); // two missing
+        assert!(regex.is_match("proogram").unwrap()); // one inserted
+        assert!(regex.is_match("pro--gram").unwrap()); // two inserted
+        assert!(!regex.is_match("prog").unwrap()); // three missing
+        assert!(!regex.is_match("pro---gram").unwrap()); // three inserted
+    }
+
+    #[test]
+    fn test_auto_case_strategy() {
+        let ascii = FuzzyConfig::builder()
+            .search_term("hello")
+            .auto_case_strategy(true)
+            .build();
+        let pattern = ascii.build_pattern().unwrap();
+        assert!(!pattern.starts_with("(?i)"));
+        assert!(pattern.contains("[hH]"));
+        assert!(ascii.is_match("HeLLo").unwrap());
+
+        let cyrillic = FuzzyConfig::builder()
+            .search_term("привет")
+            .auto_case_strategy(true)
+            .build();
+        assert!(cyrillic.build_pattern().unwrap().starts_with("(?i)"));
+        assert!(cyrillic.is_match("ПРИВЕТ").unwrap());
+    }
+
+    #[test]
+    fn test_pattern_matches_config() {
+        let config = FuzzyConfig::builder().search_term("hello").build();
+        let cached = config.build_pattern().unwrap();
+        assert!(config.pattern_matches_config(&cached));
+
+        let changed = FuzzyConfig::builder()
+            .search_term("hello")
+            .max_char_gap(2)
+            .build();
+        assert!(!changed.pattern_matches_config(&cached));
+    }
+
+    #[test]
+    fn test_trie_matcher() {
+        let dictionary = [
+            "program", "programs", "progress", "problem", "hello", "help", "helm", "world", "word",
+            "sword",
+        ];
+        let trie = TrieMatcher::new(dictionary);
+        assert_eq!(trie.len(), dictionary.len());
+
+        for (query, max_dist) in [("progam", 1), ("helo", 1), ("wrd", 2), ("xyz", 1)] {
+            let q: Vec<char> = query.chars().collect();
+            let mut expected: Vec<(&str, u32)> = dictionary
+                .iter()
+                .filter_map(|word| {
+                    let w: Vec<char> = word.chars().collect();
+                    let distance = levenshtein_table(&q, &w, true)[q.len()][w.len()] as u32;
+                    (distance <= max_dist).then_some((*word, distance))
+                })
+                .collect();
+            expected.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(b.0)));
+            assert_eq!(trie.search(query, max_dist), expected);
+        }
+
+        assert_eq!(trie.search("helo", 1)[0], ("hello", 1));
+    }
+
+    #[test]
+    fn test_streaming_fallback() {
+        let oversized = FuzzyConfig::builder()
+            .search_term("hello")
+            .max_pattern_len(10)
+            .build();
+        assert!(matches!(
+            oversized.compile(),
+            Err(FuzzyError::InvalidPattern(_))
+        ));
+        assert!(matches!(
+            oversized.is_match("hello"),
+            Err(FuzzyError::InvalidPattern(_))
+        ));
+
+        let fallback = FuzzyConfig::builder()
+            .search_term("hello")
+            .max_pattern_len(10)
+            .streaming_fallback(true)
+            .build();
+        assert!(fallback.is_match("say hello").unwrap());
+        assert!(fallback.is_match("HeLLo").unwrap());
+        assert!(fallback.is_match("help me").unwrap()); // two edits
+        assert!(!fallback.is_match("world").unwrap());
+        assert_eq!(fallback.score("hello").unwrap(), Some(1.0));
+        assert_eq!(fallback.score("world").unwrap(), None);
+    }
+
+    #[test]
+    fn test_fuzzy_match_trait() {
+        assert!("hello world".fuzzy_matches("hlo"));
+        assert!("hello world".fuzzy_matches("world"));
+        assert!(!"hello world".fuzzy_matches("xyz"));
+        assert!(!"hello world".fuzzy_matches(""));
+
+        let owned = String::from("hello");
+        assert_eq!(owned.fuzzy_score("hello"), Some(1.0));
+        assert!("say heeello".fuzzy_score("hello").unwrap() < 1.0);
+        assert_eq!("goodbye".fuzzy_score("hello"), None);
+    }
+
+    #[test]
+    fn test_per_word_case_sensitive() {
+        let config = FuzzyConfig::builder()
+            .search_term("iOS app")
+            .per_word_case_sensitive(vec![true, false])
+            .build();
+        assert!(config.is_match("iOS app").unwrap());
+        assert!(config.is_match("iOS APP").unwrap());
+        assert!(!config.is_match("ios app").unwrap());
+        assert!(!config.is_match("IOS app").unwrap());
+
+        // A mismatched length falls back to the global setting
+        let config = FuzzyConfig::builder()
+            .search_term("iOS app")
+            .per_word_case_sensitive(vec![true])
+            .build();
+        assert!(config.is_match("ios APP").unwrap());
+    }
+
+    #[test]
+    fn test_length_prefilter() {
+        let candidates = [
+            "hi",
+            "hel",
+            "hello",
+            "hello world",
+            "helo wrld",
+            "world hello",
+            "x",
+            "",
+        ];
+        let plain = FuzzyConfig::builder().search_term("hello world").build();
+        let filtered = FuzzyConfig::builder()
+            .search_term("hello world")
+            .length_prefilter(true)
+            .build();
+
+        // "hel" + " " + "wor" is the shortest possible match
+        let matcher = CompiledFuzzy::new(filtered.clone()).unwrap();
+        assert_eq!(matcher.min_len, 7);
+        assert!(!matcher.is_match("hel wo"));
+
+        assert_eq!(
+            filtered.rank(&candidates).unwrap(),
+            plain.rank(&candidates).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_anagram() {
+        let config = FuzzyConfig::builder()
+            .search_term("listen")
+            .anagram(true)
+            .build();
+        for haystack in ["silent", "enlist", "TINSEL", "a silent night"] {
+            assert!(config.is_match(haystack).unwrap(), "{}", haystack);
+        }
+        for haystack in ["listens", "lisen", "silence", "sil ent"] {
+            assert!(!config.is_match(haystack).unwrap(), "{}", haystack);
+        }
+
+        let config = FuzzyConfig::builder()
+            .search_term("evil dusty")
+            .anagram(true)
+            .build();
+        assert!(config.is_match("vile study").unwrap());
+
+        let config = FuzzyConfig::builder()
+            .search_term("a".repeat(MAX_ANAGRAM_LEN + 1))
+            .anagram(true)
+            .build();
+        assert!(matches!(
+            config.build_pattern(),
+            Err(FuzzyError::InvalidPattern(_))
+        ));
+    }
+
+    #[test]
+    fn test_char_classes() {
+        let config = FuzzyConfig::builder()
+            .search_term("gr[ae]y")
+            .required_char_ratio(1.0)
+            .char_classes(true)
+            .build();
+        assert!(config.is_match("gray").unwrap());
+        assert!(config.is_match("GREY").unwrap());
+        assert!(!config.is_match("groy").unwrap());
+
+        let config = FuzzyConfig::builder()
+            .search_term("colo[u]r")
+            .char_classes(true)
+            .required_char_ratio(1.0)
+            .build();
+        assert!(config.is_match("colour").unwrap());
+
+        let config = FuzzyConfig::builder()
+            .search_term("gr[z-a]y")
+            .char_classes(true)
+            .build();
+        assert!(matches!(
+            config.build_pattern(),
+            Err(FuzzyError::InvalidPattern(_))
+        ));
+    }
+
+    #[test]
+    fn test_matches_by_word() {
+        let config = FuzzyConfig::builder()
+            .search_term("cat dog")
+            .required_char_ratio(1.0)
+            .max_char_gap(0)
+            .build();
+        let haystack = "cat and dog, then Dog and CAT";
+
+        let words = config.matches_by_word(haystack).unwrap();
+        assert_eq!(
+            words,
+            vec![
+                ("cat".to_string(), vec![0..3, 26..29]),
+                ("dog".to_string(), vec![8..11, 18..21]),
+            ]
+        );
+
+        let words = config.matches_by_word("just a cat").unwrap();
+        assert_eq!(words[1], ("dog".to_string(), vec![]));
+    }
+
+    #[test]
+    fn test_order_bonus() {
+        let config = FuzzyConfig::builder()
+            .search_term("hello big world")
+            .ignore_word_order(true)
+            .order_bonus(0.5)
+            .build();
+        let ordered = config.score("hello big world").unwrap().unwrap();
+        let partly = config.score("hello world big").unwrap().unwrap();
+        let reversed = config.score("world big hello").unwrap().unwrap();
+        assert_eq!(ordered, 1.0);
+        assert!(ordered > partly);
+        assert!(partly > reversed);
+        assert_eq!(reversed, 0.5);
+
+        // Without a bonus both orders score the same
+        let config = FuzzyConfig::builder()
+            .search_term("hello big world")
+            .ignore_word_order(true)
+            .build();
+        assert_eq!(
+            config.score("hello big world").unwrap(),
+            config.score("world big hello").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_fuzzy_chain() {
+        let strict = FuzzyConfig::builder()
+            .search_term("hello")
+            .required_char_ratio(1.0)
+            .max_char_gap(0)
+            .build();
+        let loose = FuzzyConfig::builder()
+            .search_term("hello")
+            .max_char_gap(3)
+            .build();
+        let chain = FuzzyChain::new(vec![strict, loose], 1).unwrap();
+
+        let corpus = ["hxexlxlo", "goodbye", "hexllo"];
+        let results = chain.search(&corpus);
+        assert_eq!(results.tier, 1);
+        let indices: Vec<usize> = results.matches.iter().map(|&(i, _)| i).collect();
+        assert_eq!(indices, vec![2, 0]);
+
+        // The strict tier wins when it finds enough on its own
+        let results = chain.search(&["hello there", "hexllo"]);
+        assert_eq!(results.tier, 0);
+        assert_eq!(results.matches.len(), 1);
+
+        assert!(FuzzyChain::new(Vec::new(), 1).is_err());
+    }
+
+    #[test]
+    fn test_phone_mode() {
+        let config = FuzzyConfig::builder()
+            .search_term("5551234")
+            .phone_mode(true)
+            .build();
+        for formatted in [
+            "5551234",
+            "(555) 123-4",
+            "555-1234",
+            "555.12.34",
+            "+555 1234",
+        ] {
+            assert!(config.is_match(formatted).unwrap(), "{}", formatted);
+        }
+        assert!(!config.is_match("555-1243").unwrap());
+        assert!(!config.is_match("555-123").unwrap());
+
+        // Formatting in the term is ignored too
+        let config = FuzzyConfig::builder()
+            .search_term("call (555)123-4")
+            .phone_mode(true)
+            .build();
+        assert!(config.is_match("call 555-1234").unwrap());
+    }
+
+    #[test]
+    fn test_any_match() {
+        let config = FuzzyConfig::builder().search_term("hello").build();
+        assert!(config.any_match(&["goodbye", "hello"]).unwrap());
+        assert!(!config.any_match(&["goodbye", "farewell"]).unwrap());
+        assert!(!config.any_match(&[]).unwrap());
+
+        // The match comes first, so nothing after it is examined
+        let matcher = CompiledFuzzy::new(config).unwrap();
+        let mut visited = 0;
+        let corpus = ["hello", "goodbye", "farewell"];
+        assert!(matcher.any_match(corpus.iter().inspect(|_| visited += 1)));
+        assert_eq!(visited, 1);
+    }
+
+    #[test]
+    fn test_quoted_phrases() {
+        let config = FuzzyConfig::builder()
+            .search_term("fuzzy \"exact phrase\" words")
+            .quoted_phrases(true)
+            .build();
+        assert!(config.is_match("fuzy EXACT  phrase words").unwrap());
+        assert!(!config.is_match("fuzy exacct phrase words").unwrap());
+        assert!(!config.is_match("fuzy exact phrasse words").unwrap());
+
+        // Without the option the quoted words stay fuzzy
+        let config = FuzzyConfig::builder()
+            .search_term("fuzzy \"exact phrase\" words")
+            .build();
+        assert!(config.is_match("fuzy exacct phrase words").unwrap());
+
+        assert_eq!(
+            parse_query("a \"b c\" d", true),
+            vec![
+                QueryToken::Word("a"),
+                QueryToken::Phrase("b c"),
+                QueryToken::Word("d")
+            ]
+        );
+        // An unclosed quote is ignored
+        assert_eq!(
+            parse_query("a \"b c", true),
+            vec![
+                QueryToken::Word("a"),
+                QueryToken::Word("b"),
+                QueryToken::Word("c")
+            ]
+        );
+        // A quote inside a phrase closes it
+        assert_eq!(
+            parse_query("\"a \"b\" c\"", true),
+            vec![
+                QueryToken::Phrase("a"),
+                QueryToken::Word("b"),
+                QueryToken::Phrase("c")
+            ]
+        );
+        assert!(parse_query("\"\"", true).is_empty());
+    }
+
+    #[test]
+    fn test_required_char_count_bounds() {
+        for rounding in [
+            RatioRounding::Ceil,
+            RatioRounding::Floor,
+            RatioRounding::Round,
+        ] {
+            for ratio in [0.0, 0.1, 0.5, 0.9, 0.91, 1.0] {
+                let config = FuzzyConfig::builder()
+                    .search_term("hello")
+                    .required_char_ratio(ratio)
+                    .ratio_rounding(rounding)
+                    .build();
+                for len in 1..=7 {
+                    let required = required_char_count("hello", len, &config);
+                    assert!(required <= len, "{:?} {} {}", rounding, ratio, len);
+                }
+                assert!(config.build_pattern().is_ok());
+            }
+        }
+
+        // A ratio of 0.0 leaves every character optional
+        let none = FuzzyConfig::builder()
+            .search_term("hello")
+            .required_char_ratio(0.0)
+            .build();
+        assert_eq!(required_char_count("hello", 5, &none), 0);
+        assert_eq!(analyze_word("hello", &none).optional.len(), 5);
+
+        let config = |rounding| {
+            FuzzyConfig::builder()
+                .search_term("hello")
+                .required_char_ratio(0.5)
+                .ratio_rounding(rounding)
+                .build()
+        };
+        assert_eq!(
+            required_char_count("hello", 5, &config(RatioRounding::Ceil)),
+            3
+        );
+        assert_eq!(
+            required_char_count("hello", 5, &config(RatioRounding::Floor)),
+            2
+        );
+        assert_eq!(
+            required_char_count("hello", 5, &config(RatioRounding::Round)),
+            3
+        );
+        assert_eq!(
+            required_char_count("hello", 1, &config(RatioRounding::Floor)),
+            0
+        );
+    }
+
+    #[test]
+    fn test_weighted_search() {
+        let config = FuzzyConfig::builder().search_term("rust").build();
+        let search = WeightedSearch::new(config).unwrap();
+
+        let title_match = search
+            .score(&[("rust guide", 3.0), ("a book", 1.0)])
+            .unwrap();
+        let body_match = search
+            .score(&[("a guide", 3.0), ("all about rust", 1.0)])
+            .unwrap();
+        assert!(title_match > body_match);
+        assert_eq!(title_match, 3.0);
+
+        let both = search.score(&[("rust", 3.0), ("rust", 1.0)]).unwrap();
+        assert_eq!(both, 4.0);
+        assert_eq!(search.score(&[("python", 3.0), ("go", 1.0)]), None);
+    }
+
+    #[test]
+    fn test_tightest_span() {
+        let haystack = "hello there, and hello world";
+        let config = FuzzyConfig::builder().search_term("hello world").build();
+        let first = CompiledFuzzy::new(config.clone())
+            .unwrap()
+            .span(haystack)
+            .unwrap();
+        assert_eq!(first, 0..haystack.len());
+
+        let config = FuzzyConfig::builder()
+            .search_term("hello world")
+            .tightest_span(true)
+            .build();
+        let matcher = CompiledFuzzy::new(config).unwrap();
+        assert_eq!(&haystack[matcher.span(haystack).unwrap()], "hello world");
+        assert_eq!(matcher.score(haystack), Some(1.0));
+        assert_eq!(matcher.span("goodbye world"), None);
+    }
+
+    #[test]
+    fn test_fuzzy_similarity() {
+        assert_eq!(fuzzy_similarity("hello", "hello"), 1.0);
+        let similar = fuzzy_similarity("hello", "helo");
+        assert!(similar > 0.0 && similar < 1.0);
+        assert_eq!(fuzzy_similarity("hello", "world"), 0.0);
+        assert_eq!(fuzzy_similarity("", "hello"), 0.0);
+    }
+
+    #[test]
+    fn test_max_word_chars() {
+        let word = "abcdefghij".repeat(20);
+        let unbounded = FuzzyConfig::builder()
+            .search_term(word.as_str())
+            .build()
+            .build_pattern()
+            .unwrap();
+        let config = FuzzyConfig::builder()
+            .search_term(word.as_str())
+            .max_word_chars(8)
+            .build();
+        let bounded = config.build_pattern().unwrap();
+        assert!(bounded.len() * 10 < unbounded.len());
+
+        assert!(config.is_match(&word).unwrap());
+        assert!(config.is_match("abcdefgh").unwrap());
+        assert!(!config.is_match("abxxxxxxxxxxxxxxxx").unwrap());
+    }
+
+    #[test]
+    fn test_smart_caps() {
+        let config = FuzzyConfig::builder()
+            .search_term("HELLO")
+            .case_sensitive(true)
+            .smart_caps(true)
+            .build();
+        assert!(config.is_match("hello").unwrap());
+        assert!(config.is_match("HELLO").unwrap());
+
+        // Mixed case terms stay case-sensitive
+        let config = FuzzyConfig::builder()
+            .search_term("Hello")
+            .case_sensitive(true)
+            .smart_caps(true)
+            .build();
+        assert!(!config.is_match("hello").unwrap());
+
+        // Without smart_caps the all-caps term is case-sensitive
+        let config = FuzzyConfig::builder()
+            .search_term("HELLO")
+            .case_sensitive(true)
+            .build();
+        assert!(!config.is_match("hello").unwrap());
+    }
+
+    #[test]
+    fn test_highlight_inverse() {
+        let config = FuzzyConfig::builder().search_term("world").build();
+        assert_eq!(
+            config
+                .highlight_inverse("hello world today", "<d>", "</d>")
+                .unwrap(),
+            "<d>hello </d>world<d> today</d>"
+        );
+        assert_eq!(
+            config.highlight_inverse("world", "<d>", "</d>").unwrap(),
+            "world"
+        );
+        assert_eq!(
+            config.highlight_inverse("nothing", "<d>", "</d>").unwrap(),
+            "<d>nothing</d>"
+        );
+
+        let config = FuzzyConfig::builder()
+            .search_term("world hello")
+            .ignore_word_order(true)
+            .build();
+        assert_eq!(
+            config
+                .highlight_inverse("hello big world", "[", "]")
+                .unwrap(),
+            "hello[ big ]world"
+        );
+    }
+
+    #[test]
+    fn test_camel_case() {
+        assert_eq!(split_camel_case("JSONParser"), vec!["JSON", "Parser"]);
+        assert_eq!(split_camel_case("parseJSON"), vec!["parse", "JSON"]);
+        assert_eq!(
+            split_camel_case("getHTTPResponse2Code"),
+            vec!["get", "HTTP", "Response2", "Code"]
+        );
+        assert_eq!(split_camel_case("hello"), vec!["hello"]);
+        assert_eq!(split_camel_case("ID"), vec!["ID"]);
+
+        let config = FuzzyConfig::builder()
+            .search_term("JSONParser")
+            .camel_case(true)
+            .build();
+        assert!(config.is_match("JSONParser").unwrap());
+        assert!(config.is_match("json parser").unwrap());
+    }
+
+    #[test]
+    fn test_compiled_size() {
+        let simple = FuzzyConfig::builder().search_term("cat").build();
+        let complex = FuzzyConfig::builder()
+            .search_term("the quick brown fox jumps")
+            .leet(true)
+            .build();
+        assert!(simple.compiled_size().unwrap() < complex.compiled_size().unwrap());
+        assert!(simple.estimated_complexity() < complex.estimated_complexity());
+        assert!(simple.estimated_complexity() > 0);
+
+        let empty = FuzzyConfig::builder().search_term("").build();
+        assert!(empty.compiled_size().is_err());
+        assert_eq!(empty.estimated_complexity(), 0);
+    }
+
+    #[test]
+    fn test_normalizer() {
+        let strip_vowels = |text: &str| -> String {
+            text.to_lowercase()
+                .chars()
+                .filter(|c| !"aeiou".contains(*c))
+                .collect()
+        };
+        let config = FuzzyConfig::builder()
+            .search_term("HELLO World")
+            .normalizer(Box::new(strip_vowels))
+            .build();
+        assert_eq!(config.normalize("Hello World"), "hll wrld");
+
+        let haystack = config.normalize("hello world").into_owned();
+        assert_eq!(haystack, "hll wrld");
+        assert!(config.is_match(&haystack).unwrap());
+        assert!(config.is_match("HLL WRLD").unwrap());
+        assert!(!config.is_match("goodbye").unwrap());
+
+        let matcher = CompiledFuzzy::new(config).unwrap();
+        assert_eq!(matcher.config().search_term, "hll wrld");
+
+        // Without a normalizer text passes through unchanged
+        let config = FuzzyConfig::builder().search_term("hello").build();
+        assert!(matches!(config.normalize("Hello"), Cow::Borrowed("Hello")));
+    }
+
+    #[test]
+    fn test_best_word_match() {
+        let config = FuzzyConfig::builder().search_term("hello").build();
+        let haystack = "helo hellooo hello hello";
+        let (range, score) = config.best_word_match(haystack).unwrap();
+        assert_eq!(range, 13..18);
+        assert_eq!(&haystack[range], "hello");
+        assert_eq!(score, 1.0);
+
+        assert_eq!(config.best_word_match("goodbye world"), None);
+        let config = FuzzyConfig::builder().search_term("hello world").build();
+        assert_eq!(config.best_word_match("hello world"), None);
+    }
+
+    #[test]
+    fn test_soft_order() {
+        let config = FuzzyConfig::builder()
+            .search_term("hello world")
+            .soft_order(true)
+            .build();
+        let ordered = config.score("hello world").unwrap().unwrap();
+        let reversed = config.score("world hello").unwrap().unwrap();
+        assert_eq!(ordered, 1.0);
+        assert_eq!(reversed, 1.0 - SOFT_ORDER_BONUS);
+
+        // An explicit bonus takes precedence
+        let config = FuzzyConfig::builder()
+            .search_term("hello world")
+            .soft_order(true)
+            .order_bonus(0.5)
+            .build();
+        assert_eq!(config.score("world hello").unwrap(), Some(0.5));
+    }
+
+    #[test]
+    fn test_grep_mode() {
+        let config = FuzzyConfig::builder()
+            .search_term("hello world")
+            .grep_mode(true)
+            .build();
+        let pattern = config.build_pattern().unwrap();
+        assert!(pattern.contains("(?m)^"));
+        assert!(!pattern.contains("(?s)"));
+
+        let regex = Regex::new(&pattern).unwrap();
+        let input = "first line\nsay hello world\nhello\nworld";
+        let matching: Vec<&str> = input
+            .lines()
+            .filter(|line| regex.is_match(line).unwrap())
+            .collect();
+        assert_eq!(matching, vec!["say hello world"]);
+        // The words on separate lines never match across the break
+        assert!(!regex.is_match("hello\nworld").unwrap());
+        assert!(config.is_match("x\nhello world\ny").unwrap());
+
+        let config = FuzzyConfig::builder()
+            .search_term("hello world")
+            .grep_mode(true)
+            .ignore_word_order(true)
+            .build();
+        assert!(config.is_match("world hello").unwrap());
+        assert!(!config.is_match("world\nhello").unwrap());
+    }
+
+    #[test]
+    fn test_merge_spans() {
+        let spans = [0..3, 4..6, 10..12];
+        assert_eq!(merge_spans(&spans, 1), vec![0..6, 10..12]);
+        assert_eq!(merge_spans(&spans, 0), vec![0..3, 4..6, 10..12]);
+        assert_eq!(merge_spans(&spans, 4), vec![0..12]);
+        // Unsorted and overlapping spans
+        assert_eq!(merge_spans(&[5..8, 0..6, 1..2], 0), vec![0..8]);
+        assert!(merge_spans(&[], 3).is_empty());
+    }
+
+    #[test]
+    fn test_sentence_start() {
+        let config = FuzzyConfig::builder()
+            .search_term("the end")
+            .sentence_start(true)
+            .build();
+        assert!(config.is_match("The end is near.").unwrap());
+        assert!(config.is_match("It rained. The end came!").unwrap());
+        assert!(config.is_match("Why?The end.").unwrap());
+        assert!(!config.is_match("This is the end.").unwrap());
+
+        let matcher = CompiledFuzzy::new(config).unwrap();
+        let haystack = "It rained. The end came";
+        assert_eq!(&haystack[matcher.span(haystack).unwrap()], "The end");
+    }
+
+    #[test]
+    fn test_emoji_aware() {
+        let config = FuzzyConfig::builder()
+            .search_term("👍")
+            .emoji_aware(true)
+            .build();
+        assert!(config.is_match("great 👍").unwrap());
+        assert!(config.is_match("great 👍🏽").unwrap());
+        assert!(!config.is_match("great 👎").unwrap());
+
+        // A skin tone in the term still matches the base form
+        let config = FuzzyConfig::builder()
+            .search_term("nice👍🏿")
+            .required_char_ratio(1.0)
+            .emoji_aware(true)
+            .build();
+        assert!(config.is_match("nice👍").unwrap());
+        assert!(config.is_match("nice👍🏻").unwrap());
+        assert!(!config.is_match("nice").unwrap());
+
+        // ZWJ sequences stay a single unit
+        let config = FuzzyConfig::builder()
+            .emoji_aware(true)
+            .search_term("")
+            .build();
+        assert_eq!(split_units("a👩‍💻b", &config), vec!["a", "👩‍💻", "b"]);
+        let config = FuzzyConfig::builder()
+            .search_term("👩🏽‍💻")
+            .emoji_aware(true)
+            .build();
+        assert!(config.is_match("👩‍💻").unwrap());
+        assert!(!config.is_match("👩").unwrap());
+    }
+
+    #[test]
+    fn test_pattern_fingerprint() {
+        let config = |term: &str, gap: usize| {
+            FuzzyConfig::builder()
+                .search_term(term)
+                .max_char_gap(gap)
+                .build()
+        };
+        assert_eq!(
+            config("hello", 2).pattern_fingerprint(),
+            config("hello", 2).pattern_fingerprint()
+        );
+        assert_ne!(
+            config("hello", 2).pattern_fingerprint(),
+            config("hello", 3).pattern_fingerprint()
+        );
+        assert_ne!(
+            config("hello", 2).pattern_fingerprint(),
+            config("world", 2).pattern_fingerprint()
+        );
+        // FNV-1a of the empty string
+        assert_eq!(config("", 2).pattern_fingerprint(), 0xcbf2_9ce4_8422_2325);
+    }
+
+    #[test]
+    fn test_preserve_punctuation_position() {
+        let config = FuzzyConfig::builder()
+            .search_term("foo.bar")
+            .required_char_ratio(1.0)
+            .preserve_punctuation_position(true)
+            .build();
+        assert!(config.is_match("foo.bar").unwrap());
+        assert!(config.is_match("foobar").unwrap());
+        assert!(!config.is_match("bar.foo").unwrap());
+        assert!(!config.is_match("foo bar").unwrap());
+
+        // By default the parts may be separated by whitespace
+        let config = FuzzyConfig::builder()
+            .search_term("foo.bar")
+            .required_char_ratio(1.0)
+            .build();
+        assert!(config.is_match("foo bar").unwrap());
+        assert!(!config.is_match("bar.foo").unwrap());
+    }
+
+    #[test]
+    fn test_dedup_results() {
+        let corpus = ["hello", "help", "hello", "world", "help", "hello"];
+        let config = FuzzyConfig::builder().search_term("hello").build();
+        assert_eq!(config.rank(&corpus).unwrap().len(), 5);
+
+        let config = FuzzyConfig::builder()
+            .search_term("hello")
+            .dedup_results(true)
+            .build();
+        let ranked = config.rank(&corpus).unwrap();
+        let indices: Vec<usize> = ranked.iter().map(|&(i, _)| i).collect();
+        assert_eq!(indices, vec![0, 1]);
+        assert_eq!(ranked[0].1, 1.0);
+    }
+
+    #[test]
+    fn test_would_match_same() {
+        let config = FuzzyConfig::builder().search_term("cat").build();
+        let regex = Regex::new("(?i)c.{0,2}a.{0,2}t").unwrap();
+        assert!(config.would_match_same(&regex, &["cat", "CAT", "dog", "coat"]));
+
+        let literal = Regex::new("cat").unwrap();
+        assert!(!config.would_match_same(&literal, &["cat", "CAT"]));
+        assert!(config.would_match_same(&literal, &[]));
+    }
+
+    #[test]
+    fn test_numeric_tolerance() {
+        let config = |tolerance: f32| {
+            FuzzyConfig::builder()
+                .search_term("price $99.99")
+                .numeric_tolerance(tolerance)
+                .build()
+        };
+        assert!(config(0.01).is_match("price $100").unwrap());
+        assert!(config(0.01).is_match("price 99.5").unwrap());
+        assert!(!config(0.0001).is_match("price $100").unwrap());
+        assert!(!config(0.01).is_match("price $120").unwrap());
+        // A later number within tolerance is found after a first one outside it
+        assert!(config(0.01).is_match("price $50, sale price $100").unwrap());
+
+        assert_eq!(parse_numbers("1,200.50 and 3."), vec![1200.5, 3.0]);
+    }
+
+    #[test]
+    fn test_abbreviations() {
+        let abbreviations: HashMap<String, String> =
+            [("db".to_string(), "database".to_string())].into();
+        let config = FuzzyConfig::builder()
+            .search_term("db admin")
+            .abbreviations(abbreviations.clone())
+            .build();
+        assert!(config.is_match("database admin").unwrap());
+        assert!(config.is_match("db admin").unwrap());
+        assert!(!config.is_match("data admin").unwrap());
+
+        // Expansions also match their abbreviation
+        let config = FuzzyConfig::builder()
+            .search_term("Database")
+            .abbreviations(abbreviations)
+            .build();
+        assert!(config.is_match("DB").unwrap());
+        assert!(config.is_match("databse").unwrap());
+    }
+
+    #[test]
+    fn test_find() {
+        let config = FuzzyConfig::builder().search_term("world").build();
+        let found = config.find("hello world today").unwrap().unwrap();
+        assert_eq!((found.start, found.end), (6, 11));
+        assert_eq!(found.text, "world");
+        assert_eq!(config.find("goodbye").unwrap(), None);
+
+        // Offsets stay on char boundaries in multi-byte text
+        let config = FuzzyConfig::builder().search_term("café").build();
+        let haystack = "ünïcödé café ☕";
+        let found = config.find(haystack).unwrap().unwrap();
+        assert!(haystack.is_char_boundary(found.start));
+        assert!(haystack.is_char_boundary(found.end));
+        assert_eq!(&haystack[found.start..found.end], found.text);
+        assert!(found.text.starts_with("caf"));
+
+        assert!(FuzzyConfig::builder()
+            .search_term("")
+            .build()
+            .find("x")
+            .is_err());
+    }
+
+    #[test]
+    fn test_idf_weights() {
+        let idf: HashMap<String, f32> =
+            [("the".to_string(), 0.1), ("zebra".to_string(), 5.0)].into();
+        let config = FuzzyConfig::builder()
+            .search_term("the zebra")
+            .idf_weights(idf)
+            .build();
+        let rare_exact = config.score("txhe zebra").unwrap().unwrap();
+        let common_exact = config.score("the zexbra").unwrap().unwrap();
+        assert!(rare_exact > common_exact);
+        assert_eq!(config.score("the zebra").unwrap(), Some(1.0));
+
+        // Without weights both candidates score the same
+        let config = FuzzyConfig::builder().search_term("the zebra").build();
+        assert_eq!(
+            config.score("txhe zebra").unwrap(),
+            config.score("the zexbra").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_explain_pattern() {
+        for ignore_word_order in [false, true] {
+            let config = FuzzyConfig::builder()
+                .search_term("hello world")
+                .ignore_word_order(ignore_word_order)
+                .build();
+            let explained = config.explain_pattern().unwrap();
+            assert!(explained.starts_with("(?x)"));
+            assert!(explained.contains("# word: hello\n"));
+            assert!(explained.contains("# word: world\n"));
+
+            let regex = Regex::new(&explained).unwrap();
+            let original = config.compile().unwrap();
+            for sample in ["hello world", "helo wrld", "world hello", "goodbye"] {
+                assert_eq!(
+                    regex.is_match(sample).unwrap(),
+                    original.is_match(sample).unwrap(),
+                    "{}",
+                    sample
+                );
+            }
+        }
+
+        // Literal spaces and `#`, here from an abbreviation expansion, a custom gap
+        // and a term, still match
+        let abbreviations: HashMap<String, String> =
+            [("ny".to_string(), "new york".to_string())].into();
+        let configs = [
+            FuzzyConfig::builder()
+                .search_term("ny city")
+                .abbreviations(abbreviations)
+                .build(),
+            FuzzyConfig::builder()
+                .search_term("city")
+                .custom_gap_pattern(" ?")
+                .build(),
+            FuzzyConfig::builder()
+                .search_term("c# city")
+                .min_word_length(3)
+                .build(),
+        ];
+        for config in configs {
+            let regex = Regex::new(&config.explain_pattern().unwrap()).unwrap();
+            let original = config.compile().unwrap();
+            for sample in [
+                "new york city",
+                "ny city",
+                "c i t y",
+                "c# city",
+                "newyork city",
+            ] {
+                assert_eq!(
+                    regex.is_match(sample).unwrap(),
+                    original.is_match(sample).unwrap(),
+                    "{} on {}",
+                    config.search_term,
+                    sample
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_score_formula() {
+        for term in ["hello", "hello world", "foo.bar", "a1b2", "Ünïcode"] {
+            let config = FuzzyConfig::builder().search_term(term).build();
+            assert_eq!(config.score(term).unwrap(), Some(1.0), "{}", term);
+        }
+
+        let config = FuzzyConfig::builder().search_term("hello").build();
+        assert_eq!(config.score("hxello").unwrap(), Some(5.0 / 6.0));
+        assert_eq!(config.score("hxxello").unwrap(), Some(5.0 / 7.0));
+        assert_eq!(config.score("say hello there").unwrap(), Some(1.0));
+        assert_eq!(config.score("goodbye").unwrap(), None);
+
+        // Query syntax isn't counted as characters
+        let classes = FuzzyConfig::builder()
+            .search_term("gr[ae]y")
+            .char_classes(true)
+            .build();
+        assert_eq!(classes.score("gray").unwrap(), Some(1.0));
+        assert_eq!(classes.score("grey").unwrap(), Some(1.0));
+        let quoted = FuzzyConfig::builder()
+            .search_term("\"hello world\"")
+            .quoted_phrases(true)
+            .build();
+        assert_eq!(quoted.score("hello world").unwrap(), Some(1.0));
+        let phone = FuzzyConfig::builder()
+            .search_term("555-1234")
+            .phone_mode(true)
+            .build();
+        assert_eq!(phone.score("5551234").unwrap(), Some(1.0));
+        assert_eq!(phone.score("555-1234").unwrap(), Some(1.0));
+        assert_eq!(phone.score("(555) 123-4").unwrap(), Some(1.0));
+
+        // Character alignment and edits read the term the same way
+        assert_eq!(classes.char_matches("grey").unwrap().len(), 4);
+        assert_eq!(classes.edit_script("grey"), Some(vec![]));
+        assert_eq!(quoted.edit_script("hello world"), Some(vec![]));
+        assert_eq!(phone.edit_script("5551234"), Some(vec![]));
+        let digits: String = phone
+            .char_matches("call 555 1234")
+            .unwrap()
+            .iter()
+            .map(|m| m.query)
+            .collect();
+        assert_eq!(digits, "5551234");
+    }
+
+    #[test]
+    fn test_search() {
+        let items = [
+            "say hello there".to_string(),
+            "hxello".to_string(),
+            "goodbye".to_string(),
+            "hello".to_string(),
+        ];
+        let config = FuzzyConfig::builder().search_term("hello").build();
+        let results = config.search(items.iter().map(String::as_str)).unwrap();
+        let found: Vec<&str> = results.iter().map(|&(item, _)| item).collect();
+        // Equal scores put the shorter item first
+        assert_eq!(found, vec!["hello", "say hello there", "hxello"]);
+        assert_eq!(results[0].1, 1.0);
+    }
+
+    #[test]
+    fn test_bag_of_chars() {
+        let config = FuzzyConfig::builder()
+            .search_term("abc")
+            .bag_of_chars(true)
+            .build();
+        assert!(config.is_match("cxbxa").unwrap());
+        assert!(config.is_match("CBA").unwrap());
+        assert!(!config.is_match("ab").unwrap());
+
+        // Repeated characters must appear as often
+        let config = FuzzyConfig::builder()
+            .search_term("aab")
+            .bag_of_chars(true)
+            .build();
+        assert!(config.is_match("baxa").unwrap());
+        assert!(!config.is_match("bxa").unwrap());
+
+        let config = FuzzyConfig::builder()
+            .search_term("ab cd")
+            .bag_of_chars(true)
+            .length_prefilter(true)
+            .build();
+        assert!(config.is_match("dcba").unwrap());
+        assert!(FuzzyConfig::builder()
+            .search_term(" ")
+            .bag_of_chars(true)
+            .build()
+            .build_pattern()
+            .is_err());
+    }
+
+    #[test]
+    fn test_reject_trivial() {
+        for term in ["123", "!!!"] {
+            let config = FuzzyConfig::builder().search_term(term).build();
+            assert!(config.is_match("anything").unwrap());
+
+            let config = FuzzyConfig::builder()
+                .search_term(term)
+                .reject_trivial(true)
+                .build();
+            assert!(matches!(
+                config.build_pattern(),
+                Err(FuzzyError::TrivialPattern)
+            ));
+            assert!(matches!(config.compile(), Err(FuzzyError::TrivialPattern)));
+            assert!(config.is_match("anything").is_err());
+        }
+
+        let config = FuzzyConfig::builder()
+            .search_term("hello")
+            .reject_trivial(true)
+            .build();
+        assert!(config.compile().is_ok());
+    }
+
+    #[test]
+    fn test_new_and_with_term() {
+        let config = FuzzyConfig::new("hello");
+        assert_eq!(
+            config.build_pattern().unwrap(),
+            FuzzyConfig::builder()
+                .search_term("hello")
+                .build()
+                .build_pattern()
+                .unwrap()
+        );
+
+        let tuned = FuzzyConfig::builder()
+            .search_term("hello")
+            .max_char_gap(0)
+            .required_char_ratio(1.0)
+            .case_sensitive(true)
+            .build();
+        let world = tuned.with_term("world");
+        assert_eq!(world.search_term, "world");
+        assert_eq!(tuned.search_term, "hello");
+        assert!(world.is_match("world").unwrap());
+        assert!(!world.is_match("WORLD").unwrap());
+        assert!(!world.is_match("wxorld").unwrap());
+
+        let default = FuzzyConfig::default();
+        assert!(default.search_term.is_empty());
+        assert!(matches!(
+            default.build_pattern(),
+            Err(FuzzyError::EmptyPattern)
+        ));
+        assert_eq!(
+            default.with_term("hello").build_pattern().unwrap(),
+            config.build_pattern().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_rank_softmax() {
+        let config = FuzzyConfig::new("hello");
+        let candidates = ["hxxello", "goodbye", "hello", "hxello"];
+        let weights = config.rank_softmax(&candidates, 0.1);
+        let names: Vec<&str> = weights.iter().map(|&(name, _)| name).collect();
+        assert_eq!(names, vec!["hello", "hxello", "hxxello"]);
+        let total: f32 = weights.iter().map(|&(_, w)| w).sum();
+        assert!((total - 1.0).abs() < 1e-5);
+        assert!(weights.windows(2).all(|w| w[0].1 >= w[1].1));
 
-        assert!(regex.is_match("hello").unwrap());
-        assert!(regex.is_match("HELLO").unwrap());
-        assert!(regex.is_match("hello world").unwrap());
-        assert!(regex.is_match("say hello there").unwrap());
-        assert!(regex.is_match("heeello").unwrap()); // with extra chars
+        // Higher temperatures flatten the distribution
+        let flat = config.rank_softmax(&candidates, 10.0);
+        assert!(flat[0].1 < weights[0].1);
+        assert!(config.rank_softmax(&["goodbye"], 1.0).is_empty());
     }
 
     #[test]
-    fn test_multi_word_pattern() {
-        let pattern = FuzzyConfig::builder()
+    fn test_transposition_tolerance() {
+        let strict = FuzzyConfig::builder()
+            .search_term("the")
+            .required_char_ratio(1.0)
+            .build();
+        assert!(!strict.is_match("teh").unwrap());
+
+        let config = FuzzyConfig::builder()
+            .search_term("the")
+            .required_char_ratio(1.0)
+            .transposition_tolerance(true)
+            .build();
+        assert!(config.is_match("teh").unwrap());
+        assert!(config.is_match("hte").unwrap());
+        assert!(config.is_match("the").unwrap());
+        // Only a single adjacent swap is allowed
+        assert!(!config.is_match("eht").unwrap());
+
+        // Long words only get the capped number of alternatives
+        let long = FuzzyConfig::builder()
+            .search_term("abcdefghijklmnopqrstuvwxyz")
+            .required_char_ratio(1.0)
+            .transposition_tolerance(true)
+            .build();
+        let pattern = long.build_pattern().unwrap();
+        assert_eq!(pattern.matches('|').count(), MAX_TRANSPOSITION_PAIRS);
+        assert!(long.is_match("bacdefghijklmnopqrstuvwxyz").unwrap());
+        assert!(!long.is_match("abcdefghijklmnopqrstuvwxzy").unwrap());
+    }
+
+    #[test]
+    fn test_min_word_length_exact() {
+        let config = FuzzyConfig::builder()
+            .search_term("cat")
+            .min_word_length(4)
+            .build();
+        assert!(config.is_match("cat").unwrap());
+        assert!(config.is_match("CAT").unwrap());
+        assert!(!config.is_match("c-a-t").unwrap());
+        assert!(!config.is_match("ca").unwrap());
+
+        let config = FuzzyConfig::builder()
+            .search_term("categorize")
+            .min_word_length(4)
+            .build();
+        assert!(config.is_match("c-a-t-egorize").unwrap());
+        assert!(config.is_match("categrz").unwrap());
+
+        // Short words keep character equivalences, just not gaps or optional characters
+        let leet = FuzzyConfig::builder()
+            .search_term("cat")
+            .min_word_length(4)
+            .leet(true)
+            .build();
+        assert!(leet.is_match("c4t").unwrap());
+        assert!(!leet.is_match("c-4-t").unwrap());
+        let accents = FuzzyConfig::builder()
+            .search_term("cafe")
+            .min_word_length(5)
+            .ignore_diacritics(true)
+            .build();
+        assert!(accents.is_match("café").unwrap());
+        assert!(!accents.is_match("caf").unwrap());
+    }
+
+    #[test]
+    fn test_word_anchoring() {
+        let config = |anchor| {
+            FuzzyConfig::builder()
+                .search_term("prog")
+                .word_anchoring(anchor)
+                .build()
+        };
+        let prefix = config(WordAnchor::Prefix);
+        assert!(prefix.is_match("programming").unwrap());
+        assert!(prefix.is_match("learn programming").unwrap());
+        assert!(!prefix.is_match("reprogram").unwrap());
+        assert!(config(WordAnchor::Free).is_match("reprogram").unwrap());
+
+        let suffix = FuzzyConfig::builder()
+            .search_term("gram")
+            .required_char_ratio(1.0)
+            .word_anchoring(WordAnchor::Suffix)
+            .build();
+        assert!(suffix.is_match("reprogram now").unwrap());
+        assert!(!suffix.is_match("grammar").unwrap());
+    }
+
+    #[test]
+    fn test_char_matches() {
+        let config = FuzzyConfig::new("Hello");
+        let matches = config.char_matches("say hEllo").unwrap();
+        let kinds: Vec<CharMatchKind> = matches.iter().map(|m| m.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                CharMatchKind::CaseFold,
+                CharMatchKind::CaseFold,
+                CharMatchKind::Exact,
+                CharMatchKind::Exact,
+                CharMatchKind::Exact
+            ]
+        );
+        assert_eq!(matches[0].position, 4);
+        assert_eq!(matches[0].query, 'H');
+
+        let config = FuzzyConfig::builder()
+            .search_term("cafe")
+            .leet(true)
+            .build();
+        let kinds: Vec<CharMatchKind> = config
+            .char_matches("c4fé")
+            .unwrap()
+            .iter()
+            .map(|m| m.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                CharMatchKind::Exact,
+                CharMatchKind::Equivalence,
+                CharMatchKind::Exact,
+                CharMatchKind::DiacriticFold
+            ]
+        );
+        assert!(config.char_matches("nothing").unwrap().is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let config = FuzzyConfig::builder()
             .search_term("hello world")
-            .build()
-            .build_pattern()
-            .unwrap();
-        let regex = Regex::new(&pattern).unwrap();
+            .max_char_gap(4)
+            .required_char_ratio(0.8)
+            .case_sensitive(true)
+            .gap_by_category([(CharCategory::Digit, 2)].into())
+            .word_anchoring(WordAnchor::Prefix)
+            .max_words_between(1)
+            .build();
+        let json = serde_json::to_string(&config).unwrap();
+        let restored: FuzzyConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&restored).unwrap(), json);
+        assert_eq!(
+            restored.build_pattern().unwrap(),
+            config.build_pattern().unwrap()
+        );
 
-        assert!(regex.is_match("hello world").unwrap());
-        assert!(regex.is_match("HELLO WORLD").unwrap());
-        assert!(regex.is_match("hello there world").unwrap());
-        assert!(regex.is_match("My hello to the world").unwrap());
-        assert!(!regex.is_match("hello").unwrap()); // missing second word
-        assert!(!regex.is_match("world").unwrap()); // missing first word
+        // Only the search term is required, and other fields get builder defaults
+        let minimal: FuzzyConfig = serde_json::from_str(r#"{"search_term": "hello"}"#).unwrap();
+        assert_eq!(
+            minimal.build_pattern().unwrap(),
+            FuzzyConfig::new("hello").build_pattern().unwrap()
+        );
+        assert!(serde_json::from_str::<FuzzyConfig>("{}").is_err());
+        assert!(serde_json::from_str::<FuzzyConfig>(r#"{"max_char_gap": 2}"#).is_err());
+
+        // Ratios are clamped like the builder setter
+        let clamped: FuzzyConfig =
+            serde_json::from_str(r#"{"search_term": "hello", "required_char_ratio": 2.5}"#)
+                .unwrap();
+        assert_eq!(clamped.required_char_ratio, 1.0);
+    }
+
+    #[test]
+    fn test_ignore_word_order_permutations() {
+        let config = FuzzyConfig::builder()
+            .search_term("red green blue")
+            .ignore_word_order(true)
+            .build();
+        let pattern = config.build_pattern().unwrap();
+        assert_eq!(pattern.matches("(?=.*?").count(), 3);
+
+        let words = ["red", "green", "blue"];
+        for (a, b, c) in [
+            (0, 1, 2),
+            (0, 2, 1),
+            (1, 0, 2),
+            (1, 2, 0),
+            (2, 0, 1),
+            (2, 1, 0),
+        ] {
+            let haystack = format!("{} {} {}", words[a], words[b], words[c]);
+            assert!(config.is_match(&haystack).unwrap(), "{}", haystack);
+        }
+        assert!(!config.is_match("red green").unwrap());
+
+        // Order still matters by default
+        let ordered = FuzzyConfig::new("red green blue");
+        assert!(ordered.is_match("red green blue").unwrap());
+        assert!(!ordered.is_match("blue green red").unwrap());
+    }
+
+    #[test]
+    fn test_max_match_span() {
+        let config = FuzzyConfig::builder()
+            .search_term("alpha omega")
+            .max_match_span(20)
+            .build();
+        assert!(config.is_match("alpha and omega").unwrap());
+        let spread = format!("alpha {} omega", "filler ".repeat(10));
+        assert!(!config.is_match(&spread).unwrap());
+        // A tighter match later in the haystack is still found
+        let later = format!("{} alpha omega", spread);
+        assert_eq!(config.find(&later).unwrap().unwrap().text, "alpha omega");
+
+        let unordered = FuzzyConfig::builder()
+            .search_term("alpha omega")
+            .ignore_word_order(true)
+            .max_match_span(20)
+            .build();
+        assert!(unordered.is_match("omega, alpha").unwrap());
+        assert!(!unordered
+            .is_match(&format!("omega {} alpha", "filler ".repeat(10)))
+            .unwrap());
+    }
+
+    #[test]
+    fn test_search_display() {
+        let config = FuzzyConfig::new("fox");
+        let corpus = ["fox", "no match here", "the quick brown fox jumps"];
+        let results = config.search_display(&corpus, 3).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].candidate, "fox");
+        assert_eq!(
+            results[0].display,
+            format!("{}fox{}", ANSI_HIGHLIGHT, ANSI_RESET)
+        );
+        assert_eq!(
+            results[1].display,
+            format!("…wn {}fox{} ju…", ANSI_HIGHLIGHT, ANSI_RESET)
+        );
+        assert!(results[0].score >= results[1].score);
+
+        // Unordered words are highlighted separately
+        let config = FuzzyConfig::builder()
+            .search_term("fox quick")
+            .ignore_word_order(true)
+            .build();
+        let results = config.search_display(&corpus, 1).unwrap();
+        assert_eq!(
+            results[0].display,
+            format!(
+                "… {}quick{} brown {}fox{} …",
+                ANSI_HIGHLIGHT, ANSI_RESET, ANSI_HIGHLIGHT, ANSI_RESET
+            )
+        );
+    }
+
+    #[test]
+    fn test_anchor() {
+        let anchored = |anchor| {
+            FuzzyConfig::builder()
+                .search_term("open file")
+                .anchor(anchor)
+                .build()
+        };
+
+        let start = anchored(Anchor::Start);
+        let pattern = start.build_pattern().unwrap();
+        assert!(pattern.starts_with("(?i)(?s)^"));
+        assert!(start.is_match("OPEN File...").unwrap());
+        assert!(!start.is_match("reopen file").unwrap());
+        assert!(!start.is_match("then open file").unwrap());
+
+        let end = anchored(Anchor::End);
+        assert!(end.build_pattern().unwrap().ends_with('$'));
+        assert!(end.is_match("quick open file").unwrap());
+        assert!(!end.is_match("open file quickly").unwrap());
+
+        let exact = anchored(Anchor::Exact);
+        assert!(exact.is_match("Open  File").unwrap());
+        assert!(!exact.is_match("open file now").unwrap());
+        assert!(!exact.is_match("now open file").unwrap());
+
+        let anywhere = anchored(Anchor::Anywhere);
+        assert!(anywhere.is_match("then open file quickly").unwrap());
+
+        // Unordered terms need some word at the anchored end
+        let unordered = FuzzyConfig::builder()
+            .search_term("open file")
+            .ignore_word_order(true)
+            .anchor(Anchor::Start)
+            .build();
+        assert!(unordered.is_match("file: open").unwrap());
+        assert!(!unordered.is_match("then open file").unwrap());
+
+        // In grep mode the anchors apply per line
+        let grep = FuzzyConfig::builder()
+            .search_term("open")
+            .grep_mode(true)
+            .anchor(Anchor::Start)
+            .build();
+        assert!(grep.is_match("first line\nopen second").unwrap());
+        assert!(!grep.is_match("first line\nsecond open").unwrap());
+    }
+
+    #[test]
+    fn test_compiled_fuzzy() {
+        let config = FuzzyConfig::new("hello world");
+        let compiled = config.compiled().unwrap();
+        for haystack in ["hello world", "HELLO there world", "helo wrld", "goodbye"] {
+            assert_eq!(
+                compiled.is_match(haystack),
+                config.is_match(haystack).unwrap()
+            );
+            assert_eq!(compiled.score(haystack), config.score(haystack).unwrap());
+            assert_eq!(compiled.find(haystack), config.find(haystack).unwrap());
+        }
+
+        let cloned = compiled.clone();
+        assert_eq!(cloned.config().search_term, "hello world");
+        assert!(cloned.is_match("hello world"));
+
+        // The old name still works
+        let matcher: FuzzyMatcher = compiled;
+        assert!(matcher.is_match("hello world"));
+    }
+
+    #[test]
+    fn test_short_words_optional() {
+        let config = FuzzyConfig::builder()
+            .search_term("city of london")
+            .short_words_optional(true)
+            .build();
+        assert!(config.is_match("city of london").unwrap());
+        assert!(config.is_match("city london").unwrap());
+        assert!(!config.is_match("city of").unwrap());
+        // The short word still helps ranking when present
+        let ranked = config.rank(&["city london", "city of london"]).unwrap();
+        assert_eq!(ranked[0].0, 1);
+
+        // Leading short words and unordered terms
+        let leading = FuzzyConfig::builder()
+            .search_term("of london")
+            .short_words_optional(true)
+            .build();
+        assert!(leading.is_match("london").unwrap());
+        let unordered = FuzzyConfig::builder()
+            .search_term("london of city")
+            .short_words_optional(true)
+            .ignore_word_order(true)
+            .build();
+        assert!(unordered.is_match("city london").unwrap());
+
+        // Without the flag the short word is required
+        assert!(!FuzzyConfig::new("city of london")
+            .is_match("city london")
+            .unwrap());
+        // A term of only short words keeps them required
+        let short = FuzzyConfig::builder()
+            .search_term("to be")
+            .short_words_optional(true)
+            .build();
+        assert!(!short.is_match("to").unwrap());
+    }
+
+    #[test]
+    fn test_window_score() {
+        let config = FuzzyConfig::new("hello");
+        let haystack = "hxelxlo at the start, and much later a clean hello";
+        let whole = config.score(haystack).unwrap().unwrap();
+        let windowed = config.window_score(haystack, 10).unwrap();
+        assert!(windowed > whole);
+        assert_eq!(windowed, 1.0);
+
+        // Short haystacks are scored whole
+        assert_eq!(
+            config.window_score("hello", 50),
+            config.score("hello").unwrap()
+        );
+        assert_eq!(config.window_score("goodbye", 3), None);
+        assert_eq!(config.window_score("hello", 0), None);
+    }
+
+    #[test]
+    fn test_analyze_word() {
+        let config = FuzzyConfig::new("");
+        let analysis = analyze_word("hello", &config);
+        assert_eq!(analysis.required, vec!['h', 'e', 'l']);
+        assert_eq!(analysis.optional, vec!['l', 'o']);
+        assert_eq!(analysis.required_chars, 3);
+
+        let strict = FuzzyConfig::builder()
+            .search_term("")
+            .required_char_ratio(1.0)
+            .build();
+        assert!(analyze_word("hello", &strict).optional.is_empty());
+
+        // Short words are matched exactly
+        let analysis = analyze_word("hi", &config);
+        assert_eq!(analysis.required, vec!['h', 'i']);
+        assert_eq!(analysis.required_chars, 2);
+
+        // The analysis agrees with the generated pattern
+        let analysis = analyze_word("programming", &config);
+        let required: String = analysis.required.iter().collect();
+        let pattern = FuzzyConfig::new("programming").compile().unwrap();
+        assert!(pattern.is_match(&required).unwrap());
+        assert!(!pattern.is_match(&required[..required.len() - 1]).unwrap());
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        let config = FuzzyConfig::new("kitten");
+        assert_eq!(config.edit_distance("kitten"), 0);
+        assert_eq!(config.edit_distance("KITTEN"), 0);
+        assert_eq!(config.edit_distance("sitting"), 3);
+        // A swap of adjacent characters is one edit
+        assert_eq!(config.edit_distance("iktten"), 1);
+        assert!(config.within_distance("kiten", 1));
+        assert!(!config.within_distance("sitting", 2));
+
+        let sensitive = FuzzyConfig::builder()
+            .search_term("kitten")
+            .case_sensitive(true)
+            .build();
+        assert_eq!(sensitive.edit_distance("Kitten"), 1);
+
+        // Accented characters count as one character each
+        let config = FuzzyConfig::new("café");
+        assert_eq!(config.edit_distance("café"), 0);
+        assert_eq!(config.edit_distance("CAFÉ"), 0);
+        assert_eq!(config.edit_distance("cafe"), 1);
+        assert_eq!(config.edit_distance("caéf"), 1);
+        assert_eq!(FuzzyConfig::new("naïve").edit_distance("naive"), 1);
+    }
+
+    #[test]
+    fn test_tolerate_missing_doubles() {
+        let strict = |term: &str, tolerate| {
+            FuzzyConfig::builder()
+                .search_term(term)
+                .required_char_ratio(1.0)
+                .max_char_gap(0)
+                .tolerate_missing_doubles(tolerate)
+                .length_prefilter(true)
+                .build()
+        };
+        for (term, typo) in [
+            ("address", "adress"),
+            ("address", "addres"),
+            ("committee", "comitee"),
+            ("necessary", "necesary"),
+            ("Mississippi", "misisipi"),
+        ] {
+            let config = strict(term, true);
+            assert!(config.is_match(term).unwrap(), "{}", term);
+            assert!(config.is_match(typo).unwrap(), "{} vs {}", term, typo);
+            assert!(!strict(term, false).is_match(typo).unwrap(), "{}", typo);
+        }
+        // Only doubles may go missing
+        assert!(strict("address", true).is_match("adres").unwrap());
+        assert!(!strict("address", true).is_match("ddress").unwrap());
+    }
+
+    #[test]
+    fn test_canonical_query() {
+        assert_eq!(
+            FuzzyConfig::new("  Hello   World ").canonical_query(),
+            "hello world"
+        );
+
+        let sensitive = FuzzyConfig::builder()
+            .search_term("Hello  World")
+            .case_sensitive(true)
+            .build();
+        assert_eq!(sensitive.canonical_query(), "Hello World");
+
+        // All-caps terms fold under smart_caps
+        let smart = FuzzyConfig::builder()
+            .search_term("NASA")
+            .case_sensitive(true)
+            .smart_caps(true)
+            .build();
+        assert_eq!(smart.canonical_query(), "nasa");
+
+        let per_word = FuzzyConfig::builder()
+            .search_term("Rust Book")
+            .per_word_case_sensitive(vec![true, false])
+            .build();
+        assert_eq!(per_word.canonical_query(), "Rust book");
+
+        let normalized = FuzzyConfig::builder()
+            .search_term("Hello_World")
+            .normalizer(Box::new(|s: &str| s.replace('_', " ")))
+            .build();
+        assert_eq!(normalized.canonical_query(), "hello world");
+
+        // Equal canonical queries agree on matches
+        let a = FuzzyConfig::new("Hello World");
+        let b = FuzzyConfig::new("hello   world");
+        assert_eq!(a.canonical_query(), b.canonical_query());
+        assert_eq!(a.build_pattern().unwrap(), b.build_pattern().unwrap());
+    }
+
+    #[test]
+    fn test_gap_allows_whitespace() {
+        let legacy = |gap| {
+            FuzzyConfig::builder()
+                .search_term("hello")
+                .required_char_ratio(1.0)
+                .max_char_gap(gap)
+                .build()
+        };
+        let explicit = |gap, allows| {
+            FuzzyConfig::builder()
+                .search_term("hello")
+                .required_char_ratio(1.0)
+                .max_char_gap(gap)
+                .gap_allows_whitespace(allows)
+                .build()
+        };
+        // The legacy threshold switches behavior between 10 and 11
+        assert!(!legacy(10).is_match("he llo").unwrap());
+        assert!(legacy(11).is_match("he llo").unwrap());
+
+        // The flag decides regardless of the gap size
+        assert!(explicit(2, true).is_match("he llo").unwrap());
+        assert!(!explicit(2, true).is_match("he   llo").unwrap());
+        assert!(!explicit(11, false).is_match("he llo").unwrap());
+        assert!(explicit(11, false).is_match("he-llo").unwrap());
+    }
+
+    #[test]
+    fn test_highlight_segments() {
+        let segment = |text: &str, matched| Segment {
+            text: text.to_string(),
+            matched,
+        };
+        let config = FuzzyConfig::new("world");
+        assert_eq!(
+            config.highlight("hello world bye").unwrap(),
+            vec![
+                segment("hello ", false),
+                segment("world", true),
+                segment(" bye", false)
+            ]
+        );
+        assert_eq!(
+            config.highlight("world").unwrap(),
+            vec![segment("world", true)]
+        );
+        assert_eq!(
+            config.highlight("nothing").unwrap(),
+            vec![segment("nothing", false)]
+        );
+
+        let config = FuzzyConfig::builder()
+            .search_term("fox brown")
+            .ignore_word_order(true)
+            .build();
+        for text in ["  the brown\tquick\n fox  ", "", "brown fox", "no match"] {
+            let segments = config.highlight(text).unwrap();
+            let joined: String = segments.iter().map(|s| s.text.as_str()).collect();
+            assert_eq!(joined, text);
+            assert!(segments.iter().all(|s| !s.text.is_empty()));
+            assert!(segments.windows(2).all(|w| w[0].matched != w[1].matched));
+        }
+        let segments = config.highlight("the brown quick fox").unwrap();
+        assert_eq!(segments.iter().filter(|s| s.matched).count(), 2);
+    }
+
+    #[test]
+    fn test_multi_term_config() {
+        let base = FuzzyConfig::builder()
+            .search_term("")
+            .required_char_ratio(1.0)
+            .max_char_gap(0)
+            .build();
+        let config = MultiTermConfig::new(base, ["color", "colour"]);
+        let pattern = config.build_pattern().unwrap();
+        assert!(pattern.starts_with("(?i)(?:"));
+        assert_eq!(pattern.matches("(?i)").count(), 1);
+
+        assert!(config.is_match("Color me surprised").unwrap());
+        assert!(config.is_match("the COLOUR of money").unwrap());
+        assert!(!config.is_match("a flavour of the week").unwrap());
+        assert!(!config.is_match("").unwrap());
+
+        // Terms needing different case flags keep them scoped
+        let base = FuzzyConfig::builder()
+            .search_term("")
+            .case_sensitive(true)
+            .smart_caps(true)
+            .build();
+        let config = MultiTermConfig::new(base, ["NASA", "Esa"]);
+        assert!(config.is_match("nasa").unwrap());
+        assert!(config.is_match("Esa").unwrap());
+        assert!(!config.is_match("esa").unwrap());
+
+        let empty = MultiTermConfig::new(FuzzyConfig::new(""), Vec::<String>::new());
+        assert!(matches!(
+            empty.build_pattern(),
+            Err(FuzzyError::EmptyPattern)
+        ));
+    }
+
+    #[test]
+    fn test_keyboard_layout() {
+        let config = |layout: Option<KeyboardLayout>| {
+            let builder = FuzzyConfig::builder()
+                .search_term("apple")
+                .required_char_ratio(1.0)
+                .max_char_gap(0);
+            match layout {
+                Some(layout) => builder.keyboard_layout(layout).build(),
+                None => builder.build(),
+            }
+        };
+        let qwerty = config(Some(KeyboardLayout::Qwerty));
+        assert!(qwerty.build_pattern().unwrap().contains("[aAqQwWsSzZ]"));
+        assert!(qwerty.is_match("apple").unwrap());
+        // "s" and "o" sit next to "a" and "p"
+        assert!(qwerty.is_match("spple").unwrap());
+        assert!(qwerty.is_match("aoole").unwrap());
+        assert!(!qwerty.is_match("gpple").unwrap());
+        // Gaps stay as strict as without the layout
+        assert!(!qwerty.is_match("a pple").unwrap());
+
+        assert!(!config(None).is_match("spple").unwrap());
+        assert_eq!(KeyboardLayout::Qwerty.neighbors('A'), "qwsz");
+        assert_eq!(KeyboardLayout::Qwerty.neighbors('1'), "");
+
+        let matches = qwerty.char_matches("spple").unwrap();
+        assert_eq!(matches[0].kind, CharMatchKind::Equivalence);
+    }
+
+    #[test]
+    fn test_find_all() {
+        let config = FuzzyConfig::builder()
+            .search_term("hello")
+            .required_char_ratio(1.0)
+            .max_char_gap(0)
+            .build();
+        let haystack = "hello world, HELLO again, and héllo";
+        let ranges: Vec<_> = config
+            .find_all(haystack)
+            .unwrap()
+            .iter()
+            .map(|m| m.start..m.end)
+            .collect();
+        assert_eq!(ranges, vec![0..5, 13..18]);
+        assert!(config.find_all("goodbye").unwrap().is_empty());
+
+        // Overlapping candidates are skipped past the end of each match
+        let config = FuzzyConfig::builder()
+            .search_term("aba")
+            .required_char_ratio(1.0)
+            .max_char_gap(0)
+            .build();
+        let matches = config.find_all("ababa").unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!((matches[0].start, matches[0].end), (0, 3));
+        assert_eq!(config.find_all("aba aba").unwrap().len(), 2);
+
+        // Unordered terms give at most one match
+        let config = FuzzyConfig::builder()
+            .search_term("b a")
+            .ignore_word_order(true)
+            .build();
+        assert_eq!(config.find_all("a b a b").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(FuzzyConfig::new("hello").validate().is_ok());
+        assert!(matches!(
+            FuzzyConfig::new("   ").validate(),
+            Err(FuzzyError::EmptyPattern)
+        ));
+
+        let huge_gap = FuzzyConfig::builder()
+            .search_term("hello")
+            .max_char_gap(usize::MAX)
+            .build();
+        assert!(matches!(
+            huge_gap.validate(),
+            Err(FuzzyError::InvalidPattern(message)) if message.contains("gap")
+        ));
+        assert!(matches!(
+            huge_gap.build_pattern(),
+            Err(FuzzyError::InvalidPattern(_))
+        ));
+        assert!(huge_gap.is_match("hello").is_err());
+
+        let category_gap = FuzzyConfig::builder()
+            .search_term("hello")
+            .gap_by_category(HashMap::from([(CharCategory::Punct, MAX_CHAR_GAP + 1)]))
+            .build();
+        assert!(category_gap.build_pattern().is_err());
+
+        // The gap derived from a fuzziness budget is capped too
+        let huge_budget = FuzzyConfig::builder()
+            .search_term("hello")
+            .fuzziness_budget(100_000)
+            .build();
+        assert!(matches!(
+            huge_budget.validate(),
+            Err(FuzzyError::InvalidPattern(message)) if message.contains("100000")
+        ));
+        assert!(huge_budget.compiled().is_err());
+
+        // Ratios set without the clamping builder setter are still checked
+        let nan_ratio = FuzzyConfig {
+            required_char_ratio: f32::NAN,
+            ..FuzzyConfig::new("hello")
+        };
+        assert!(matches!(
+            nan_ratio.build_pattern(),
+            Err(FuzzyError::InvalidPattern(message)) if message.contains("required_char_ratio")
+        ));
+
+        let negative = FuzzyConfig::builder()
+            .search_term("$100")
+            .numeric_tolerance(-0.5)
+            .build();
+        assert!(negative.build_pattern().is_err());
+    }
+
+    #[test]
+    fn test_max_pattern_complexity() {
+        let term = "the quick brown fox jumps over the lazy dog";
+        let complexity = FuzzyConfig::new(term).estimated_complexity();
+        assert!(complexity > FuzzyConfig::new("fox").estimated_complexity());
+
+        let capped = |max| {
+            FuzzyConfig::builder()
+                .search_term(term)
+                .max_pattern_complexity(max)
+                .build()
+        };
+        assert!(capped(complexity).build_pattern().is_ok());
+        assert!(capped(complexity).is_match(term).unwrap());
+        assert!(matches!(
+            capped(complexity - 1).build_pattern(),
+            Err(FuzzyError::InvalidPattern(message)) if message.contains("complexity")
+        ));
+        assert!(capped(complexity - 1).compile().is_err());
+        assert!(capped(complexity - 1).is_match(term).is_err());
+        // The estimate stays available for rejected configs
+        assert_eq!(capped(complexity - 1).estimated_complexity(), complexity);
+    }
+
+    #[test]
+    fn test_require_full_word() {
+        let config = |full| {
+            FuzzyConfig::builder()
+                .search_term("programming")
+                .max_char_gap(1)
+                .require_full_word(full)
+                .build()
+        };
+        assert!(config(false).is_match("program").unwrap());
+        assert!(!config(true).is_match("program").unwrap());
+        assert!(!config(true).build_pattern().unwrap().contains(")?)"));
+        assert!(config(true).is_match("programming").unwrap());
+        assert!(config(true).is_match("programmming").unwrap());
+        assert!(config(true).is_match("PROGRAMMING").unwrap());
+    }
+
+    #[test]
+    fn test_try_fuzzy_search_pattern() {
+        assert_eq!(
+            try_fuzzy_search_pattern("hello").unwrap(),
+            fuzzy_search_pattern("hello")
+        );
+        assert!(matches!(
+            try_fuzzy_search_pattern("  "),
+            Err(FuzzyError::EmptyPattern)
+        ));
+        // The infallible version keeps its empty fallback
+        assert_eq!(fuzzy_search_pattern(""), "");
     }
 
-    #[test]
-    fn test_short_word_pattern() {
-        let pattern = FuzzyConfig::builder()
-            .search_term("hi")
-            .build()
-            .build_pattern()
-            .unwrap();
-        let regex = Regex::new(&pattern).unwrap();
+    #[cfg(feature = "unicode-normalization")]
+    #[test]
+    fn test_unicode_normalization() {
+        let decomposed = "cafe\u{301}";
+        let precomposed = "caf\u{e9}";
+        let strict = FuzzyConfig::builder()
+            .search_term(decomposed)
+            .required_char_ratio(1.0)
+            .build();
+        assert!(!strict.is_match(&format!("un {}", precomposed)).unwrap());
+
+        let config = FuzzyConfig::builder()
+            .search_term(decomposed)
+            .required_char_ratio(1.0)
+            .normalize(Normalization::Nfc)
+            .build();
+        assert!(config.build_pattern().unwrap().contains('\u{e9}'));
+        assert!(config.is_match(&format!("un {}", precomposed)).unwrap());
+        // Haystacks are normalized separately
+        let haystack = format!("un {}", decomposed);
+        assert_eq!(config.normalize(&haystack), format!("un {}", precomposed));
+        assert!(config.is_match(&config.normalize(&haystack)).unwrap());
+
+        let nfkc = FuzzyConfig::builder()
+            .search_term("\u{fb01}le")
+            .normalize(Normalization::Nfkc)
+            .build();
+        assert_eq!(nfkc.canonical_query(), "file");
 
-        assert!(regex.is_match("hi").unwrap());
-        assert!(regex.is_match("HI").unwrap());
-        assert!(regex.is_match("this").unwrap());
-        assert!(regex.is_match("history").unwrap());
-        assert!(regex.is_match("hi there").unwrap());
+        // Decomposed haystacks, such as macOS file names, match a precomposed term
+        let nfd = FuzzyConfig::builder()
+            .search_term(precomposed)
+            .required_char_ratio(1.0)
+            .normalize(Normalization::Nfd)
+            .build();
+        assert!(nfd.build_pattern().unwrap().contains('\u{301}'));
+        assert!(nfd.is_match(&format!("{}.txt", decomposed)).unwrap());
+        assert_eq!(nfd.normalize(precomposed), decomposed);
+
+        let nfkd = FuzzyConfig::builder()
+            .search_term("\u{fb01}l\u{e9}")
+            .normalize(Normalization::Nfkd)
+            .build();
+        assert_eq!(nfkd.canonical_query(), "file\u{301}");
+        assert!(nfkd.is_match("file\u{301}").unwrap());
     }
 
     #[test]
-    fn test_long_word_pattern() {
-        let pattern = FuzzyConfig::builder()
-            .search_term("programming")
-            .build()
-            .build_pattern()
-            .unwrap();
-        let regex = Regex::new(&pattern).unwrap();
-
-        assert!(regex.is_match("programming").unwrap());
-        assert!(regex.is_match("PROGRAMMING").unwrap());
-        assert!(regex.is_match("programmming").unwrap()); // typo
-        assert!(regex.is_match("program").unwrap()); // partial match is ok
+    fn test_is_match_bytes() {
+        let config = FuzzyConfig::new("error");
+        assert!(config.is_match_bytes(b"log: ERROR at boot").unwrap());
+        assert!(config.is_match_bytes(b"\xff\xfe error \xc0").unwrap());
+        assert!(!config.is_match_bytes(b"\xff\xfe\xc0\x80 warn").unwrap());
+        assert!(!config.is_match_bytes(&[0xff; 64]).unwrap());
+        assert!(!config.is_match_bytes(b"").unwrap());
     }
 
     #[test]
-    fn test_case_sensitivity() {
-        let pattern = FuzzyConfig::builder()
-            .search_term("Test")
+    fn test_case_fold() {
+        let config = |term: &str, fold| {
+            FuzzyConfig::builder()
+                .search_term(term)
+                .required_char_ratio(1.0)
+                .max_char_gap(0)
+                .case_fold(fold)
+                .build()
+        };
+        let full = config("straße", CaseFold::Full);
+        assert!(full.is_match("Straße").unwrap());
+        assert!(full.is_match("STRASSE").unwrap());
+        assert!(full.is_match("strasse").unwrap());
+        assert!(!config("straße", CaseFold::Simple)
+            .is_match("strasse")
+            .unwrap());
+
+        // Ligatures expand too
+        let ligature = config("ﬁne", CaseFold::Full);
+        assert!(ligature.is_match("fine").unwrap());
+        assert!(ligature.is_match("FINE").unwrap());
+        assert_eq!(ligature.find("define").unwrap().unwrap().text, "fine");
+
+        // Case-sensitive matching doesn't fold
+        let sensitive = FuzzyConfig::builder()
+            .search_term("straße")
+            .required_char_ratio(1.0)
+            .max_char_gap(0)
             .case_sensitive(true)
-            .build()
-            .build_pattern()
-            .unwrap();
-        let regex = Regex::new(&pattern).unwrap();
+            .case_fold(CaseFold::Full)
+            .build();
+        assert!(!sensitive.is_match("strasse").unwrap());
 
-        assert!(regex.is_match("Test").unwrap());
-        assert!(!regex.is_match("test").unwrap());
-        assert!(!regex.is_match("TEST").unwrap());
-        assert!(!regex.is_match("testing").unwrap());
+        // Folded characters are required like letters
+        assert!(!full.is_match("strae").unwrap());
+        assert!(config("straße", CaseFold::Simple)
+            .is_match("strae")
+            .unwrap());
+
+        // Turkish dotted and dotless I
+        let dotted = config("İstanbul", CaseFold::Full);
+        assert!(dotted.is_match("istanbul").unwrap());
+        assert!(dotted.is_match("İSTANBUL").unwrap());
+        assert!(dotted.is_match("i\u{307}stanbul").unwrap());
+        assert!(!dotted.is_match("Istanbul").unwrap());
+        let plain = config("istanbul", CaseFold::Full);
+        assert!(plain.is_match("İstanbul").unwrap());
+        assert!(plain.is_match("ISTANBUL").unwrap());
+        assert!(!config("istanbul", CaseFold::Simple)
+            .is_match("İstanbul")
+            .unwrap());
+        let dotless = config("ılık", CaseFold::Full);
+        assert!(dotless.is_match("ILIK").unwrap());
+        assert!(!dotless.is_match("ilik").unwrap());
     }
 
     #[test]
-    fn test_custom_config() {
-        let pattern = FuzzyConfig::builder()
+    fn test_custom_gap_pattern() {
+        let config = |gap: &str| {
+            FuzzyConfig::builder()
+                .search_term("hello")
+                .required_char_ratio(1.0)
+                .custom_gap_pattern(gap)
+                .build()
+        };
+        let hyphens = config("-?");
+        assert!(hyphens.build_pattern().unwrap().contains("[hH](?:-?)[eE]"));
+        assert!(hyphens.is_match("h-e-l-l-o").unwrap());
+        assert!(hyphens.is_match("hello").unwrap());
+        assert!(!hyphens.is_match("h_e_l_l_o").unwrap());
+
+        // The fragment applies even where max_char_gap would allow nothing
+        let letters = FuzzyConfig::builder()
             .search_term("hello")
-            .min_word_length(5)
-            .required_char_ratio(0.8)
-            .max_char_gap(2)
-            .build()
-            .build_pattern()
-            .unwrap();
-        println!("Generated pattern: {}", pattern);
-        let regex = Regex::new(&pattern).unwrap();
+            .required_char_ratio(1.0)
+            .max_char_gap(0)
+            .custom_gap_pattern("[a-z]{0,2}")
+            .build();
+        assert!(letters.is_match("hxexllo").unwrap());
+        assert!(!letters.is_match("h1ello").unwrap());
 
-        assert!(regex.is_match("hello").unwrap());
-        assert!(regex.is_match("heello").unwrap()); // small gap
-        assert!(!regex.is_match("h e l l o").unwrap()); // too big gaps
+        for invalid in ["(", "[a-", "a{2,1}"] {
+            assert!(matches!(
+                config(invalid).build_pattern(),
+                Err(FuzzyError::InvalidPattern(_))
+            ));
+        }
 
-        // Debug prints for failing case
-        println!("Testing 'h e l l o' against pattern");
-        println!("Pattern matches: {}", regex.is_match("h e l l o").unwrap());
+        // Fragments that would break out of their slot in the word pattern
+        for (gap, problem) in [
+            (".)|(.", "closes a group"),
+            ("-|_", "alternates"),
+            ("x)", "closes a group"),
+        ] {
+            assert!(matches!(
+                config(gap).validate(),
+                Err(FuzzyError::InvalidPattern(message)) if message.contains(problem)
+            ));
+        }
+
+        // Capturing groups would shift the capture indices of spans
+        for gap in ["(-)?", "(?<dash>-)?", "(?P<dash>-)?"] {
+            assert!(matches!(
+                config(gap).build_pattern(),
+                Err(FuzzyError::InvalidPattern(message)) if message.contains("capturing")
+            ));
+        }
+
+        // Groups, alternation within them, escapes and classes are fine
+        for gap in [
+            "(?:-|_)?",
+            "\\(?",
+            "[|()]?",
+            "[]|]?",
+            "(?<=\\w)-?",
+            "(?i:x)?",
+        ] {
+            assert!(config(gap).validate().is_ok(), "{} was rejected", gap);
+        }
+        assert!(config("(?:-|_)?").is_match("h-e_llo").unwrap());
     }
 
+    #[cfg(feature = "rayon")]
     #[test]
-    fn test_empty_pattern() {
-        let result = FuzzyConfig::builder()
-            .search_term("")
-            .build()
-            .build_pattern();
-        assert!(matches!(result, Err(FuzzyError::EmptyPattern)));
+    fn test_par_search() {
+        let items = [
+            "item hello world",
+            "helo wrld",
+            "unrelated entry",
+            "HELLO there, world",
+            "hello world",
+        ];
+        let matcher = FuzzyConfig::new("hello world").compiled().unwrap();
+        let parallel = matcher.par_search(&items);
+        assert_eq!(parallel, matcher.search(items.iter().copied()));
+        assert_eq!(parallel.len(), 3);
+        assert_eq!(parallel[0].0, "hello world");
     }
 
     #[test]
-    fn test_whitespace_only_pattern() {
-        let result = FuzzyConfig::builder()
-            .search_term("   ")
-            .build()
-            .build_pattern();
-        assert!(matches!(result, Err(FuzzyError::EmptyPattern)));
+    fn test_word_patterns() {
+        let config = FuzzyConfig::new("quick fox");
+        let words = config.word_patterns().unwrap();
+        assert_eq!(words.len(), 2);
+        let pattern = config.build_pattern().unwrap();
+        assert!(words.iter().all(|word| pattern.contains(word.as_str())));
+        assert!(words.iter().all(|word| !word.starts_with("(?i)")));
+
+        // The pieces can be recombined with a custom separator
+        let recombined = Regex::new(&format!("(?i){}\\s*,\\s*{}", words[0], words[1])).unwrap();
+        assert!(recombined.is_match("Quick , FOX").unwrap());
+        assert!(!recombined.is_match("quick fox").unwrap());
+
+        assert!(matches!(
+            FuzzyConfig::new("").word_patterns(),
+            Err(FuzzyError::EmptyPattern)
+        ));
     }
 
     #[test]
-    fn test_builder_methods() {
-        let config = FuzzyConfig::builder()
-            .search_term("test")
-            .min_word_length(4)
-            .required_char_ratio(0.75)
-            .case_sensitive(true)
-            .max_char_gap(3)
-            .build();
+    fn test_ignore_diacritics() {
+        let config = |term: &str, ignore, case_sensitive| {
+            FuzzyConfig::builder()
+                .search_term(term)
+                .required_char_ratio(1.0)
+                .max_char_gap(0)
+                .case_sensitive(case_sensitive)
+                .ignore_diacritics(ignore)
+                .build()
+        };
+        let cafe = config("cafe", true, false);
+        assert!(cafe.build_pattern().unwrap().contains("[eEèÈéÉ"));
+        for haystack in ["café", "CAFÉ", "cafè", "cafe", "çafe"] {
+            assert!(cafe.is_match(haystack).unwrap(), "{}", haystack);
+        }
+        assert!(!config("cafe", false, false).is_match("café").unwrap());
+        for (term, haystack) in [("naive", "naïve"), ("senor", "señor"), ("Zurich", "Zürich")] {
+            assert!(
+                config(term, true, false).is_match(haystack).unwrap(),
+                "{}",
+                haystack
+            );
+        }
 
-        let pattern = config.build_pattern().unwrap();
-        let regex = Regex::new(&pattern).unwrap();
+        // Accents in the term are stripped, precomposed or combining
+        assert!(config("café", true, false).is_match("cafe").unwrap());
+        assert!(config("cafe\u{301}", true, false).is_match("cafè").unwrap());
+        assert_eq!(config("Café", true, false).canonical_query(), "cafe");
 
-        assert!(regex.is_match("test").unwrap());
-        assert!(!regex.is_match("TEST").unwrap()); // case sensitive
+        // Case-sensitive terms keep the case of each letter
+        let sensitive = config("Ecole", true, true);
+        assert!(sensitive.is_match("École").unwrap());
+        assert!(!sensitive.is_match("école").unwrap());
     }
 
     #[test]
-    fn test_special_characters() {
-        let pattern = FuzzyConfig::builder()
-            .search_term("hello.world$^")
-            .build()
-            .build_pattern()
-            .unwrap();
-        let regex = Regex::new(&pattern).unwrap();
+    fn test_word_scores() {
+        let config = FuzzyConfig::new("quick brown fox");
+        let scores = config.word_scores("the quick bro_wn dog").unwrap();
+        let words: Vec<&str> = scores.iter().map(|(word, _)| word.as_str()).collect();
+        assert_eq!(words, vec!["quick", "brown", "fox"]);
+        assert_eq!(scores[0].1, 1.0);
+        assert!(scores[1].1 > 0.0 && scores[1].1 < 1.0);
+        assert_eq!(scores[2].1, 0.0);
 
-        assert!(regex.is_match("hello.world$^").unwrap());
-        assert!(regex.is_match("hello world").unwrap()); // Still matches without special chars
-        assert!(regex.is_match("hello...world").unwrap()); // Matches with extra dots
+        // The best occurrence of each word counts
+        let scores = config.word_scores("qxuxick then quick").unwrap();
+        assert_eq!(scores[0].1, 1.0);
     }
 
     #[test]
-    fn test_unicode_characters() {
-        let pattern = FuzzyConfig::builder()
-            .search_term("привет мир")
-            .build()
-            .build_pattern()
-            .unwrap();
-        let regex = Regex::new(&pattern).unwrap();
+    fn test_presets() {
+        let strict = FuzzyConfig::preset(Preset::Strict);
+        assert_eq!(strict.required_char_ratio, 1.0);
+        assert_eq!(strict.max_char_gap, 0);
+        let typo = FuzzyConfig::preset(Preset::Typo);
+        assert_eq!((typo.required_char_ratio, typo.max_char_gap), (0.8, 1));
+        let loose = FuzzyConfig::preset(Preset::Loose);
+        assert_eq!((loose.required_char_ratio, loose.max_char_gap), (0.4, 10));
+        let autocomplete = FuzzyConfig::preset(Preset::Autocomplete);
+        assert_eq!(autocomplete.anchor, Anchor::Start);
+        assert_eq!(autocomplete.required_char_ratio, 0.6);
 
-        assert!(regex.is_match("привет мир").unwrap());
-        assert!(regex.is_match("ПРИВЕТ МИР").unwrap());
-        assert!(regex.is_match("привет добрый мир").unwrap());
+        let strict = strict.with_term("hello");
+        assert!(strict.is_match("say hello").unwrap());
+        assert!(!strict.is_match("say helo").unwrap());
+        assert!(!strict.is_match("say hel-lo").unwrap());
+        assert!(typo.with_term("hello").is_match("say hel-lo").unwrap());
+
+        let autocomplete = autocomplete.with_term("hel");
+        assert!(autocomplete.is_match("hello world").unwrap());
+        assert!(!autocomplete.is_match("say hello").unwrap());
     }
 
     #[test]
-    fn test_extreme_char_gaps() {
-        let pattern = FuzzyConfig::builder()
-            .search_term("test")
-            .max_char_gap(100)
-            .build()
-            .build_pattern()
-            .unwrap();
-        let regex = Regex::new(&pattern).unwrap();
+    fn test_digits_required() {
+        let config = |term: &str, required: bool| {
+            FuzzyConfig::builder()
+                .search_term(term)
+                .digits_required(required)
+                .build()
+        };
+        // Digits stay optional by default
+        assert!(config("test123", false).is_match("test").unwrap());
+        assert!(!config("test123", true).is_match("test").unwrap());
+        assert!(config("test123", true).is_match("test123").unwrap());
 
-        assert!(regex.is_match("t e s t").unwrap());
-        assert!(regex.is_match("t....e....s....t").unwrap());
+        let part = config("A1234", true);
+        assert!(part.is_match("part A1234 in stock").unwrap());
+        assert!(part.is_match("a1234").unwrap());
+        assert!(!part.is_match("A12").unwrap());
+        assert!(!part.is_match("A1235").unwrap());
+        assert!(config("A1234", false).is_match("A12").unwrap());
+    }
 
-        // Test with minimum gap
-        let pattern = FuzzyConfig::builder()
-            .search_term("test")
-            .max_char_gap(0)
-            .build()
-            .build_pattern()
-            .unwrap();
+    #[test]
+    fn test_and() {
+        let hello = FuzzyConfig::new("hello");
+        let world = FuzzyConfig::new("world");
+        let pattern = hello.and(&world).unwrap();
+        assert!(pattern.starts_with("(?s)^(?=.*?"));
         let regex = Regex::new(&pattern).unwrap();
+        assert!(regex.is_match("hello world").unwrap());
+        assert!(regex.is_match("WORLD, hello").unwrap());
+        assert!(!regex.is_match("hello there").unwrap());
+        assert!(!regex.is_match("world").unwrap());
 
-        assert!(regex.is_match("test").unwrap());
-        assert!(!regex.is_match("t e s t").unwrap());
+        // Each config keeps its own case sensitivity
+        let strict = FuzzyConfig::builder()
+            .search_term("Hello")
+            .case_sensitive(true)
+            .build();
+        let regex = Regex::new(&strict.and(&world).unwrap()).unwrap();
+        assert!(regex.is_match("Hello WORLD").unwrap());
+        assert!(!regex.is_match("hello world").unwrap());
+
+        assert!(matches!(
+            hello.and(&FuzzyConfig::new("")),
+            Err(FuzzyError::EmptyPattern)
+        ));
     }
 
     #[test]
-    fn test_extreme_word_lengths() {
-        // Very short word
-        let pattern = FuzzyConfig::builder()
-            .search_term("a")
-            .build()
-            .build_pattern()
-            .unwrap();
-        let regex = Regex::new(&pattern).unwrap();
+    fn test_max_typos() {
+        let config = |typos: usize| {
+            FuzzyConfig::builder()
+                .search_term("hello")
+                .required_char_ratio(0.2)
+                .max_typos(typos)
+                .build()
+        };
+        // max_typos wins over the low ratio
+        assert_eq!(analyze_word("hello", &config(0)).required_chars, 5);
+        assert!(config(0).is_match("hello").unwrap());
+        assert!(!config(0).is_match("hell").unwrap());
 
-        assert!(regex.is_match("a").unwrap());
-        assert!(regex.is_match("abc").unwrap());
+        assert_eq!(analyze_word("hello", &config(1)).required_chars, 4);
+        assert!(config(1).is_match("hell").unwrap());
+        assert!(!config(1).is_match("hel").unwrap());
 
-        // Very long word
-        let long_word = "a".repeat(100);
-        let pattern = FuzzyConfig::builder()
-            .search_term(&long_word)
-            .build()
-            .build_pattern()
-            .unwrap();
-        let regex = Regex::new(&pattern).unwrap();
+        assert_eq!(analyze_word("hello", &config(2)).required_chars, 3);
+        assert!(config(2).is_match("hel").unwrap());
+        assert!(!config(2).is_match("he").unwrap());
 
-        assert!(regex.is_match(&long_word).unwrap());
-        assert!(regex.is_match(&format!("{}b", &long_word)).unwrap());
+        // Clamped to keep the first character
+        assert_eq!(analyze_word("hello", &config(10)).required_chars, 1);
     }
 
     #[test]
-    fn test_boundary_char_ratio() {
-        // Test with 100% ratio
-        let pattern = FuzzyConfig::builder()
-            .search_term("test")
-            .required_char_ratio(1.0)
-            .build()
-            .build_pattern()
+    fn test_search_lines() {
+        let matcher = FuzzyConfig::new("hello").compiled().unwrap();
+        let input = "first line\r\nsay hello\nnothing\nHELLO again";
+        let lines: Vec<(usize, String)> = matcher
+            .search_lines(input.as_bytes())
+            .collect::<io::Result<_>>()
             .unwrap();
-        let regex = Regex::new(&pattern).unwrap();
-
-        assert!(regex.is_match("test").unwrap());
-        assert!(!regex.is_match("tes").unwrap()); // Won't match with missing char
+        assert_eq!(
+            lines,
+            vec![(2, "say hello".to_string()), (4, "HELLO again".to_string())]
+        );
 
-        // Test with minimum ratio
-        let pattern = FuzzyConfig::builder()
-            .search_term("test")
-            .required_char_ratio(0.0)
-            .build()
-            .build_pattern()
+        // Long lines are cut to MAX_LINE_LEN without losing the following lines
+        let long = format!("{}hello\nhello\n", "x".repeat(MAX_LINE_LEN));
+        let lines: Vec<(usize, String)> = matcher
+            .search_lines(long.as_bytes())
+            .collect::<io::Result<_>>()
             .unwrap();
-        let regex = Regex::new(&pattern).unwrap();
+        assert_eq!(lines, vec![(2, "hello".to_string())]);
 
-        assert!(regex.is_match("test").unwrap());
-        assert!(regex.is_match("t").unwrap()); // Matches with just first char
+        // Read errors are yielded once, then iteration stops
+        struct Failing;
+        impl io::Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("disk gone"))
+            }
+        }
+        let mut results = matcher.search_lines(io::BufReader::new(Failing));
+        assert!(results.next().unwrap().is_err());
+        assert!(results.next().is_none());
     }
 
     #[test]
-    fn test_multiple_spaces() {
-        let pattern = FuzzyConfig::builder()
-            .search_term("hello   world") // Multiple spaces
-            .build()
-            .build_pattern()
-            .unwrap();
-        let regex = Regex::new(&pattern).unwrap();
+    fn test_single_char_case_insensitive() {
+        let config = FuzzyConfig::new("A");
+        assert!(config.is_match("apple").unwrap());
+        assert!(config.is_match("BANANA").unwrap());
 
-        assert!(regex.is_match("hello world").unwrap());
-        assert!(regex.is_match("hello   world").unwrap());
-        assert!(regex.is_match("hello \t\n world").unwrap()); // Different whitespace
+        // The single-character branch, reached when short words are fuzzy
+        let config = FuzzyConfig::builder()
+            .search_term("A")
+            .min_word_length(1)
+            .build();
+        assert!(config.build_pattern().unwrap().contains("[aA]"));
+        assert!(config.is_match("apple").unwrap());
+        assert!(config.is_match("BANANA").unwrap());
+        assert!(!config.is_match("cherry").unwrap());
+
+        let sensitive = FuzzyConfig::builder()
+            .search_term("A")
+            .min_word_length(1)
+            .case_sensitive(true)
+            .build();
+        assert!(!sensitive.is_match("apple").unwrap());
+        assert!(sensitive.is_match("BANANA").unwrap());
+
+        // Characters whose uppercase is several characters stay whole
+        let single = |term: &str| {
+            FuzzyConfig::builder()
+                .search_term(term)
+                .min_word_length(1)
+                .build()
+        };
+        assert!(single("ß").is_match("straße").unwrap());
+        assert!(single("ß").is_match("STRASSE").unwrap());
+        assert!(!single("ß").is_match("S").unwrap());
+        assert!(single("ﬁ").is_match("ﬁle").unwrap());
+        assert!(single("ﬁ").is_match("FIX").unwrap());
+        assert!(!single("ﬁ").is_match("F").unwrap());
     }
 
     #[test]
-    fn test_numbers_and_mixed_content() {
-        let pattern = FuzzyConfig::builder()
-            .search_term("test123 456")
-            .build()
-            .build_pattern()
-            .unwrap();
-        let regex = Regex::new(&pattern).unwrap();
+    fn test_describe() {
+        let config = FuzzyConfig::builder()
+            .search_term("hello")
+            .required_char_ratio(0.6)
+            .max_char_gap(1)
+            .build();
+        assert_eq!(
+            config.describe(),
+            "Matching 'hello' case-insensitively, requiring first 3 of 5 characters, \
+             allowing up to 1 non-space character between each"
+        );
 
-        assert!(regex.is_match("test123 456").unwrap());
-        assert!(regex.is_match("test 123 456").unwrap());
-        assert!(regex.is_match("TEST123 456").unwrap());
+        let config = FuzzyConfig::builder()
+            .search_term("Big cat")
+            .case_sensitive(true)
+            .required_char_ratio(1.0)
+            .max_char_gap(0)
+            .ignore_word_order(true)
+            .min_word_length(4)
+            .build();
+        assert_eq!(
+            config.describe(),
+            "Matching 'Big' case-sensitively exactly; \
+             matching 'cat' case-sensitively exactly; words in any order"
+        );
+
+        let config = FuzzyConfig::builder()
+            .search_term("world")
+            .required_char_ratio(1.0)
+            .max_char_gap(20)
+            .build();
+        assert!(config
+            .describe()
+            .ends_with("requiring all 5 characters, allowing up to 20 characters between each"));
     }
 
     #[test]
-    fn test_logging() {
-        use env_logger;
-        let _ = env_logger::builder().is_test(true).try_init();
-
-        let pattern = FuzzyConfig::builder()
-            .search_term("test")
-            .max_char_gap(2)
-            .min_word_length(3)
-            .required_char_ratio(0.8)
-            .build()
-            .build_pattern()
-            .unwrap();
+    fn test_single_line() {
+        let config = |term: &str, single_line: bool, ignore_order: bool| {
+            FuzzyConfig::builder()
+                .search_term(term)
+                .single_line(single_line)
+                .ignore_word_order(ignore_order)
+                .build()
+        };
+        let split = "say hello\n\nworld peace";
+        assert!(config("hello world", false, false).is_match(split).unwrap());
+        let strict = config("hello world", true, false);
+        assert!(!strict.build_pattern().unwrap().contains("(?s)"));
+        assert!(!strict.is_match(split).unwrap());
+        assert!(strict.is_match("intro\nsay hello world\nbye").unwrap());
 
-        let regex = Regex::new(&pattern).unwrap();
-        assert!(regex.is_match("test").unwrap());
+        // Unordered words must share a line too
+        assert!(config("world hello", false, true).is_match(split).unwrap());
+        let unordered = config("world hello", true, true);
+        assert!(!unordered.is_match(split).unwrap());
+        assert!(unordered.is_match("intro\nhello world\nbye").unwrap());
+        assert_eq!(
+            unordered
+                .compiled()
+                .unwrap()
+                .find("intro\nhello world")
+                .map(|m| m.text),
+            Some("hello world".to_string())
+        );
+    }
 
-        // Test error logging
-        let result = FuzzyConfig::builder()
-            .search_term("")
-            .build()
-            .build_pattern();
-        assert!(matches!(result, Err(FuzzyError::EmptyPattern)));
+    #[test]
+    fn test_length_prefilter_abbreviations() {
+        let abbreviations: HashMap<String, String> =
+            [("db".to_string(), "database".to_string())].into();
+        let config = |prefilter: bool| {
+            FuzzyConfig::builder()
+                .search_term("database")
+                .abbreviations(abbreviations.clone())
+                .length_prefilter(prefilter)
+                .build()
+        };
+        assert!(config(false).is_match("db").unwrap());
+        assert!(config(true).is_match("db").unwrap());
+        assert_eq!(CompiledFuzzy::new(config(true)).unwrap().min_len, 2);
+        assert!(!config(true).is_match("d").unwrap());
     }
 }